@@ -0,0 +1,125 @@
+//! Storage of management tokens and database access keys, and their
+//! refresh after the server rejects them
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::config::WireKVSConfig;
+use crate::error::WireKVSError;
+use crate::retry;
+
+type RefreshFn = dyn Fn() -> Pin<Box<dyn Future<Output = Option<String>> + Send>> + Send + Sync;
+
+/// Callback that fetches a new token, set with `WireKVSBuilder::on_unauthorized`
+#[derive(Clone)]
+pub(crate) struct Refresh(Arc<RefreshFn>);
+
+impl Refresh {
+    pub(crate) fn new<F, Fut>(refresh: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        Refresh(Arc::new(move || Box::pin(refresh())))
+    }
+}
+
+type KeyRefreshFn = dyn Fn(String) -> Pin<Box<dyn Future<Output = Option<String>> + Send>> + Send + Sync;
+
+/// Callback that fetches a new access key for the database with the given
+/// id, set with `WireKVSBuilder::on_access_key_rejected`
+#[derive(Clone)]
+pub(crate) struct KeyRefresh(Arc<KeyRefreshFn>);
+
+impl KeyRefresh {
+    pub(crate) fn new<F, Fut>(refresh: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        KeyRefresh(Arc::new(move |id| Box::pin(refresh(id))))
+    }
+
+    /// The refresh for a single database, asking for the key of `id`
+    pub(crate) fn for_database(&self, id: &str) -> Refresh {
+        let refresh = self.0.clone();
+        let id = id.to_string();
+        Refresh(Arc::new(move || refresh(id.clone())))
+    }
+}
+
+impl fmt::Debug for KeyRefresh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyRefresh(..)")
+    }
+}
+
+impl fmt::Debug for Refresh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Refresh(..)")
+    }
+}
+
+/// The token or access key sent with requests, replaced when a refresh succeeds
+pub(crate) struct Token {
+    value: RwLock<String>,
+    refresh: Option<Refresh>,
+    // Serializes refreshes so concurrent 401s only fetch one new token
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl Token {
+    pub(crate) fn new(value: String, refresh: Option<Refresh>) -> Self {
+        Token {
+            value: RwLock::new(value),
+            refresh,
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn current(&self) -> String {
+        self.value.read().unwrap().clone()
+    }
+
+    /// Sends the request built by `request` with the current token
+    ///
+    /// On a 401, the refresh callback is asked for a new token and the request
+    /// is sent once more with it. Without a callback, or when it returns
+    /// `None`, the 401 response is passed on unchanged.
+    pub(crate) async fn send(
+        &self,
+        config: &WireKVSConfig,
+        idempotent: bool,
+        request: impl Fn(&str) -> RequestBuilder,
+    ) -> Result<Response, WireKVSError> {
+        let rejected = self.current();
+        let response = retry::send(config, idempotent, request(&rejected)).await?;
+        if response.status() != StatusCode::UNAUTHORIZED || self.refresh.is_none() {
+            return Ok(response);
+        }
+        match self.refreshed(&rejected).await {
+            // The server turned the request away, so sending it again is safe
+            // even for writes
+            Some(token) => retry::send(config, idempotent, request(&token)).await,
+            None => Ok(response),
+        }
+    }
+
+    async fn refreshed(&self, rejected: &str) -> Option<String> {
+        let refresh = self.refresh.as_ref()?;
+        let _guard = self.refreshing.lock().await;
+        let current = self.current();
+        if current != rejected {
+            // Another request already refreshed the token while we waited
+            return Some(current);
+        }
+        debug!("token rejected, refreshing it");
+        let token = (refresh.0)().await?;
+        *self.value.write().unwrap() = token.clone();
+        Some(token)
+    }
+}
@@ -0,0 +1,394 @@
+//! Blocking wrappers around the async API
+//!
+//! These types mirror `wirekvs::WireKVS` and `wirekvs::WireKVSDatabase` for
+//! programs that don't run inside a Tokio runtime. Each client drives the
+//! async implementation on a private runtime, which also keeps the event socket
+//! alive in the background between calls.
+//!
+//! The blocking and async variants can't share a client instance: a blocking
+//! handle owns its runtime, and its methods panic if called from within an
+//! async context.
+//!
+//! # Example
+//! ```no_run
+//! use serde_json::json;
+//! use wirekvs::blocking::WireKVSDatabase;
+//!
+//! let db = WireKVSDatabase::new("database-id".to_string(), "access-key".to_string()).unwrap();
+//! db.set("greeting", json!("Hello!")).unwrap();
+//! let value = db.get("greeting").unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+
+use crate::{CreatedDatabase, DatabaseConfig, DatabaseConfigUpdate, DatabaseInfo, EventEnvelope, Page, SetResponse, WireKVSConfig, WireKVSError};
+
+fn runtime() -> Arc<Runtime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .expect("failed to build Tokio runtime");
+    Arc::new(runtime)
+}
+
+/// Forwards a broadcast receiver into a standard channel until either side closes
+fn forward<T: Clone + Send + 'static>(runtime: &Runtime, mut rx: broadcast::Receiver<T>) -> mpsc::Receiver<T> {
+    let (tx, std_rx) = mpsc::channel();
+    runtime.spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    std_rx
+}
+
+/// Blocking counterpart of `wirekvs::WireKVSDatabase`
+///
+/// Clones share the same runtime and event socket.
+#[derive(Clone)]
+pub struct WireKVSDatabase {
+    inner: crate::WireKVSDatabase,
+    runtime: Arc<Runtime>,
+}
+
+impl WireKVSDatabase {
+    /// Creates a new WireKVSDatabase instance
+    pub fn new(id: String, access_key: String) -> Result<Self, WireKVSError> {
+        Self::with_config(id, access_key, WireKVSConfig::default())
+    }
+
+    /// Creates a new WireKVSDatabase instance using custom connection settings
+    pub fn with_config(id: String, access_key: String, config: WireKVSConfig) -> Result<Self, WireKVSError> {
+        let runtime = runtime();
+        let inner = runtime.block_on(crate::WireKVSDatabase::with_config(id, access_key, config))?;
+        Ok(WireKVSDatabase { inner, runtime })
+    }
+
+    /// Gets all entries from the database
+    pub fn get_all_entries(&self) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.get_all_entries())
+    }
+
+    /// Lists every key in the database, in lexicographic order
+    pub fn keys(&self) -> Result<Vec<String>, WireKVSError> {
+        self.runtime.block_on(self.inner.keys())
+    }
+
+    /// Lists the keys that start with `prefix`, in lexicographic order
+    pub fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, WireKVSError> {
+        self.runtime.block_on(self.inner.keys_with_prefix(prefix))
+    }
+
+    /// Gets one page of entries
+    pub fn get_entries_page(&self, cursor: Option<String>, limit: usize) -> Result<Page, WireKVSError> {
+        self.runtime.block_on(self.inner.get_entries_page(cursor, limit))
+    }
+
+    /// Gets a specific value by key
+    pub fn get(&self, key: &str) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.get(key))
+    }
+
+    /// Gets a value by key, or `None` if the key doesn't exist
+    pub fn get_opt(&self, key: &str) -> Result<Option<Value>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_opt(key))
+    }
+
+    /// Gets a value along with the `ETag` the server sent for it
+    pub fn get_with_etag(&self, key: &str) -> Result<(Value, Option<String>), WireKVSError> {
+        self.runtime.block_on(self.inner.get_with_etag(key))
+    }
+
+    /// Gets a value only if it changed since `etag` was issued
+    pub fn get_if_modified(&self, key: &str, etag: &str) -> Result<Option<Value>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_if_modified(key, etag))
+    }
+
+    /// Checks whether a key exists without fetching its value
+    pub fn exists(&self, key: &str) -> Result<bool, WireKVSError> {
+        self.runtime.block_on(self.inner.exists(key))
+    }
+
+    /// Gets a value by key and deserializes it into `T`
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, WireKVSError> {
+        self.runtime.block_on(self.inner.get_as(key))
+    }
+
+    /// Gets a string value, or `None` if the key doesn't exist
+    pub fn get_string(&self, key: &str) -> Result<Option<String>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_string(key))
+    }
+
+    /// Gets an integer value, or `None` if the key doesn't exist
+    pub fn get_i64(&self, key: &str) -> Result<Option<i64>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_i64(key))
+    }
+
+    /// Gets a number as a float, or `None` if the key doesn't exist
+    pub fn get_f64(&self, key: &str) -> Result<Option<f64>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_f64(key))
+    }
+
+    /// Gets a bool value, or `None` if the key doesn't exist
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_bool(key))
+    }
+
+    /// Sets a value for a specific key
+    pub fn set(&self, key: &str, value: Value) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.set(key, value))
+    }
+
+    /// Sets a value and returns the write metadata sent back by the server
+    pub fn set_returning(&self, key: &str, value: Value) -> Result<SetResponse, WireKVSError> {
+        self.runtime.block_on(self.inner.set_returning(key, value))
+    }
+
+    /// Sets a value, sending `idempotency_key` so the server can drop duplicate writes
+    pub fn set_idempotent(&self, key: &str, value: Value, idempotency_key: &str) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.set_idempotent(key, value, idempotency_key))
+    }
+
+    /// Serializes `value` and sets it for a specific key
+    pub fn set_typed<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.set_typed(key, value))
+    }
+
+    /// Sets a value given as already serialized JSON, sending it unchanged
+    pub fn set_raw(&self, key: &str, json: &str) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.set_raw(key, json))
+    }
+
+    /// Sets `new` only if the current value equals `expected`
+    pub fn compare_and_set(&self, key: &str, expected: Option<Value>, new: Value) -> Result<bool, WireKVSError> {
+        self.runtime.block_on(self.inner.compare_and_set(key, expected, new))
+    }
+
+    /// Sets a value only if the key doesn't exist yet, returning whether it was written
+    pub fn set_if_absent(&self, key: &str, value: Value) -> Result<bool, WireKVSError> {
+        self.runtime.block_on(self.inner.set_if_absent(key, value))
+    }
+
+    /// Adds `delta` to an integer value and returns the new total
+    pub fn increment(&self, key: &str, delta: i64) -> Result<i64, WireKVSError> {
+        self.runtime.block_on(self.inner.increment(key, delta))
+    }
+
+    /// Appends `element` to an array value and returns the resulting array
+    pub fn array_append(&self, key: &str, element: Value) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.array_append(key, element))
+    }
+
+    /// Removes every element equal to `element` from an array value and
+    /// returns the resulting array
+    pub fn array_remove(&self, key: &str, element: &Value) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.array_remove(key, element))
+    }
+
+    /// Replaces a value with the result of `f` applied to the current value
+    pub fn update<F>(&self, key: &str, f: F) -> Result<Value, WireKVSError>
+    where
+        F: FnMut(Option<Value>) -> Value,
+    {
+        self.runtime.block_on(self.inner.update(key, f))
+    }
+
+    /// Gets many keys at once, leaving out keys that don't exist
+    pub fn get_many(&self, keys: &[&str]) -> Result<HashMap<String, Value>, WireKVSError> {
+        self.runtime.block_on(self.inner.get_many(keys))
+    }
+
+    /// Sets many keys at once
+    pub fn set_many(&self, entries: HashMap<String, Value>) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.set_many(entries))
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to a value and returns the result
+    pub fn patch(&self, key: &str, patch: Value) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.patch(key, patch))
+    }
+
+    /// Deletes a value by key
+    pub fn delete(&self, key: &str) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.delete(key))
+    }
+
+    /// Moves the value stored under `from` to `to`
+    pub fn rename(&self, from: &str, to: &str, overwrite: bool) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.rename(from, to, overwrite))
+    }
+
+    /// Deletes many keys at once
+    pub fn delete_many(&self, keys: &[&str]) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.delete_many(keys))
+    }
+
+    /// Deletes every key in the database
+    pub fn clear(&self) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.clear())
+    }
+
+    /// Sends a JSON text frame to the server over the event socket
+    pub fn send_ws(&self, message: Value) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.send_ws(message))
+    }
+
+    /// Closes the event socket with a proper close handshake
+    pub fn close(&mut self) {
+        self.runtime.block_on(self.inner.close())
+    }
+
+    /// Stops every background task of this handle and closes the event socket
+    pub fn shutdown(&self) {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+
+    /// Id of the database this handle operates on
+    pub fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    /// Access key this handle authenticates with
+    pub fn access_key(&self) -> String {
+        self.inner.access_key()
+    }
+
+    /// Whether the event socket is currently live
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    /// Subscribe to real-time database events
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn example(db: wirekvs::blocking::WireKVSDatabase) {
+    /// let rx = db.subscribe();
+    /// std::thread::spawn(move || {
+    ///     for event in rx {
+    ///         println!("Event: {:?}", event);
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> mpsc::Receiver<Value> {
+        forward(&self.runtime, self.inner.subscribe())
+    }
+
+    /// Subscribe to real-time database events parsed into `Event`, each
+    /// paired with its raw frame
+    pub fn subscribe_typed(&self) -> mpsc::Receiver<EventEnvelope> {
+        forward(&self.runtime, self.inner.subscribe_typed())
+    }
+}
+
+/// Blocking counterpart of `wirekvs::WireKVS`
+pub struct WireKVS {
+    inner: crate::WireKVS,
+    runtime: Arc<Runtime>,
+}
+
+impl WireKVS {
+    /// Creates a new WireKVS client instance
+    pub fn new(token: String) -> Self {
+        WireKVS {
+            inner: crate::WireKVS::new(token),
+            runtime: runtime(),
+        }
+    }
+
+    /// Creates a new WireKVS client instance using custom connection settings
+    pub fn with_config(token: String, config: WireKVSConfig) -> Result<Self, WireKVSError> {
+        Ok(WireKVS {
+            inner: crate::WireKVS::with_config(token, config)?,
+            runtime: runtime(),
+        })
+    }
+
+    /// Lists all databases for the authenticated user
+    pub fn list_databases(&self) -> Result<Value, WireKVSError> {
+        self.runtime.block_on(self.inner.list_databases())
+    }
+
+    /// Reads the current settings and metadata of a database
+    pub fn get_database_info(&self, id: &str) -> Result<DatabaseInfo, WireKVSError> {
+        self.runtime.block_on(self.inner.get_database_info(id))
+    }
+
+    /// Changes a database's access settings and returns the resulting config
+    pub fn update_database_config(
+        &self,
+        id: &str,
+        update: impl Into<DatabaseConfigUpdate>,
+    ) -> Result<DatabaseConfig, WireKVSError> {
+        self.runtime.block_on(self.inner.update_database_config(id, update))
+    }
+
+    /// Checks that the backend is reachable and the token is accepted
+    pub fn health_check(&self) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.health_check())
+    }
+
+    /// Creates a new database with specified configuration
+    pub fn create_database(&self, name: &str, config: DatabaseConfig) -> Result<CreatedDatabase, WireKVSError> {
+        self.runtime.block_on(self.inner.create_database(name, config))
+    }
+
+    /// Creates a new database and connects to it
+    pub fn create_and_connect(&self, name: &str, config: DatabaseConfig) -> Result<WireKVSDatabase, WireKVSError> {
+        let inner = self.runtime.block_on(self.inner.create_and_connect(name, config))?;
+        Ok(WireKVSDatabase {
+            inner,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Deletes a database by ID
+    pub fn delete_database(&self, id: &str) -> Result<(), WireKVSError> {
+        self.runtime.block_on(self.inner.delete_database(id))
+    }
+
+    /// Gets a database instance for direct operations
+    ///
+    /// The returned handle shares this client's runtime and HTTP connection pool.
+    pub fn database(&self, id: String, access_key: String) -> Result<WireKVSDatabase, WireKVSError> {
+        let inner = self.runtime.block_on(self.inner.database(id, access_key))?;
+        Ok(WireKVSDatabase {
+            inner,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Connects to several databases concurrently, given as `(id, access_key)` pairs
+    pub fn connect_many(&self, creds: Vec<(String, String)>) -> Result<Vec<WireKVSDatabase>, WireKVSError> {
+        let inner = self.runtime.block_on(self.inner.connect_many(creds))?;
+        Ok(inner
+            .into_iter()
+            .map(|inner| WireKVSDatabase {
+                inner,
+                runtime: self.runtime.clone(),
+            })
+            .collect())
+    }
+
+    /// Gets a database instance for a database returned by `create_database`
+    pub fn connect(&self, database: &CreatedDatabase) -> Result<WireKVSDatabase, WireKVSError> {
+        self.database(database.id.clone(), database.access_key.clone())
+    }
+}
@@ -0,0 +1,348 @@
+use std::future::Future;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+use crate::auth::{KeyRefresh, Refresh, Token};
+use crate::config::{ReconnectPolicy, RetryPolicy, SocketAuth, WireKVSConfig};
+use crate::{Certificate, ConcurrencyLimit, RateLimiter, WireKVS, WireKVSError};
+
+/// Builder for a `WireKVS` client
+///
+/// Every setting except the token is optional and defaults to the values of
+/// `WireKVSConfig::default()`.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use wirekvs::WireKVS;
+///
+/// let client = WireKVS::builder()
+///     .token("auth-token")
+///     .timeout(Duration::from_secs(10))
+///     .user_agent("my-app/1.0")
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WireKVSBuilder {
+    token: Option<String>,
+    config: WireKVSConfig,
+    http_client: Option<reqwest::Client>,
+    on_unauthorized: Option<Refresh>,
+    on_access_key_rejected: Option<KeyRefresh>,
+}
+
+impl WireKVSBuilder {
+    /// Creates a builder with default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the auth token used for database management requests
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Fetches a new token whenever the server rejects the current one
+    ///
+    /// When a management request comes back with HTTP 401, `refresh` is
+    /// awaited and the request is sent once more with the token it returns,
+    /// which also replaces the stored token for later requests. If `refresh`
+    /// returns `None`, the request fails with `WireKVSError::Unauthorized`.
+    /// Concurrent requests rejected with the same token share one refresh.
+    ///
+    /// Only the client's token is refreshed; for database access keys, see
+    /// `on_access_key_rejected`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::WireKVS;
+    /// # async fn fetch_token() -> Option<String> { None }
+    /// let client = WireKVS::builder()
+    ///     .token("auth-token")
+    ///     .on_unauthorized(|| async { fetch_token().await })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn on_unauthorized<F, Fut>(mut self, refresh: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        self.on_unauthorized = Some(Refresh::new(refresh));
+        self
+    }
+
+    /// Fetches a new access key whenever the server rejects a database's current one
+    ///
+    /// Works like `on_unauthorized`, for databases opened through this
+    /// client: when one of their requests comes back with HTTP 401,
+    /// `refresh` is called with the database id, and the request is sent
+    /// once more with the key it returns, which the handle and all its clones
+    /// use from then on. If `refresh` returns `None`, the request fails with
+    /// `WireKVSError::Unauthorized`. Uploads from `set_stream` are not
+    /// repeated, since their body can only be sent once.
+    ///
+    /// The event socket picks up the new key the next time it reconnects.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::WireKVS;
+    /// # async fn fetch_access_key(id: &str) -> Option<String> { None }
+    /// let client = WireKVS::builder()
+    ///     .token("auth-token")
+    ///     .on_access_key_rejected(|id| async move { fetch_access_key(&id).await })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn on_access_key_rejected<F, Fut>(mut self, refresh: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        self.on_access_key_rejected = Some(KeyRefresh::new(refresh));
+        self
+    }
+
+    /// Replaces all settings with `config`
+    pub fn config(mut self, config: WireKVSConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the base URL of the HTTP API
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.config.base_url = base_url.into();
+        self
+    }
+
+    /// Sets the base URL of the event socket
+    pub fn ws_base_url(mut self, ws_base_url: impl Into<String>) -> Self {
+        self.config.ws_base_url = ws_base_url.into();
+        self
+    }
+
+    /// Appends a query parameter to the event socket URL
+    ///
+    /// Can be called repeatedly; see `WireKVSConfig::ws_query_params`.
+    pub fn ws_query_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.ws_query_params.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets how the event socket handshake carries the access key
+    pub fn ws_auth(mut self, auth: SocketAuth) -> Self {
+        self.config.ws_auth = auth;
+        self
+    }
+
+    /// Offers a subprotocol in the event socket handshake
+    ///
+    /// Can be called repeatedly; see `WireKVSConfig::ws_subprotocols`.
+    pub fn ws_subprotocol(mut self, protocol: impl Into<String>) -> Self {
+        self.config.ws_subprotocols.push(protocol.into());
+        self
+    }
+
+    /// Sets the time limit for each HTTP request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the largest HTTP response body read, in bytes
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.config.max_response_size = bytes;
+        self
+    }
+
+    /// Unwraps values the server sends inside a metadata envelope, see
+    /// `WireKVSConfig::value_envelopes`
+    pub fn value_envelopes(mut self, enabled: bool) -> Self {
+        self.config.value_envelopes = enabled;
+        self
+    }
+
+    /// Sets how many idle HTTP connections are kept open per host
+    ///
+    /// The pool is shared by every database opened from the client. Lower
+    /// this to hold fewer sockets open when many databases are used in bursts.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.config.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Sets how long idle HTTP connections are kept, `None` keeps them forever
+    pub fn pool_idle_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.config.pool_idle_timeout = timeout.into();
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with every request
+    ///
+    /// Replaces the default `wirekvs-rust/<crate version>`, for HTTP requests
+    /// and the event socket handshake alike.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Sets extra headers merged into every HTTP request
+    ///
+    /// The crate's own `Authorization` header is never replaced by these.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.config.default_headers = headers;
+        self
+    }
+
+    /// Routes plain HTTP requests and `ws://` event sockets through `proxy`
+    ///
+    /// Setting any proxy here disables the `HTTP_PROXY`/`HTTPS_PROXY`
+    /// environment variables, which are honored by default.
+    pub fn http_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.http_proxy = Some(proxy.into());
+        self
+    }
+
+    /// Routes HTTPS requests and `wss://` event sockets through `proxy`
+    pub fn https_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.https_proxy = Some(proxy.into());
+        self
+    }
+
+    /// Sets comma-separated hosts that bypass the configured proxies
+    pub fn no_proxy(mut self, hosts: impl Into<String>) -> Self {
+        self.config.no_proxy = Some(hosts.into());
+        self
+    }
+
+    /// Trusts `certificate` as an additional root CA for HTTPS and `wss://`
+    ///
+    /// Can be called repeatedly to add several CAs. Any host presenting a
+    /// certificate signed by one of them is trusted, so only add CAs you
+    /// control.
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.config.root_certificates.push(certificate);
+        self
+    }
+
+    /// Accepts invalid TLS certificates. **Never use this in production.**
+    ///
+    /// With this enabled, expired, self-signed and mismatched certificates
+    /// are accepted for HTTP requests and the event socket alike, so anyone
+    /// on the network path can impersonate the server and steal credentials.
+    /// It exists for local test servers with throwaway certificates.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.config.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Sets how failed HTTP requests are retried
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Limits how fast requests are sent, shared by every database of this client
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.config.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Caps how many requests are in flight at once, shared by every database of this client
+    pub fn concurrency_limit(mut self, limit: ConcurrencyLimit) -> Self {
+        self.config.concurrency_limit = Some(limit);
+        self
+    }
+
+    /// Sets how the event socket reconnects after it drops
+    pub fn reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.config.reconnect = reconnect;
+        self
+    }
+
+    /// Re-reads all entries after every reconnect and emits them as `set` events
+    ///
+    /// Each resync waits a random delay of up to `max_jitter` first, so
+    /// clients reconnecting together don't all fetch at once. It costs a full
+    /// `get_all_entries` download, so it's off unless enabled here.
+    pub fn resync_on_reconnect(mut self, max_jitter: Duration) -> Self {
+        self.config.resync_on_reconnect = Some(max_jitter);
+        self
+    }
+
+    /// Sets how often the event socket is pinged, `Duration::ZERO` disables keepalive
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.config.ping_interval = interval;
+        self
+    }
+
+    /// Sets how long to wait for a pong before reconnecting
+    pub fn pong_timeout(mut self, timeout: Duration) -> Self {
+        self.config.pong_timeout = timeout;
+        self
+    }
+
+    /// Sets how many events each subscriber buffers before it starts lagging
+    pub fn event_buffer_size(mut self, size: usize) -> Self {
+        self.config.event_buffer_size = size;
+        self
+    }
+
+    /// Sets how many requests batch operations keep in flight at once
+    pub fn batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.config.batch_concurrency = batch_concurrency;
+        self
+    }
+
+    /// Caches up to `capacity` `get` results in memory for each database
+    ///
+    /// See `WireKVSConfig::cache_capacity` for the consistency guarantees.
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.config.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Caches values this client writes with `set`, see `WireKVSConfig::read_your_writes`
+    #[cfg(feature = "cache")]
+    pub fn read_your_writes(mut self, enabled: bool) -> Self {
+        self.config.read_your_writes = enabled;
+        self
+    }
+
+    /// Sends all requests through an existing `reqwest::Client`
+    ///
+    /// The supplied client's own settings take precedence: `timeout`, the
+    /// pool and TLS settings, `user_agent`, `default_headers` and the proxies
+    /// set on this builder are not applied to it. The event socket still uses
+    /// the proxy and TLS settings.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Validates the settings and builds the client
+    ///
+    /// Fails with `WireKVSError::InvalidConfig` if no token was set, a base
+    /// or proxy URL doesn't parse, the timeout or event buffer size is zero, or
+    /// the rate limit is not positive.
+    pub fn build(self) -> Result<WireKVS, WireKVSError> {
+        let token = match self.token {
+            Some(token) if !token.is_empty() => token,
+            _ => return Err(WireKVSError::InvalidConfig("a token is required".to_string())),
+        };
+        self.config.validate()?;
+        let mut client = match self.http_client {
+            Some(client) => WireKVS::with_http_client(token, client, self.config),
+            None => WireKVS::with_config(token, self.config)?,
+        };
+        if let Some(refresh) = self.on_unauthorized {
+            client.token = Token::new(client.token.current(), Some(refresh));
+        }
+        client.key_refresh = self.on_access_key_rejected;
+        Ok(client)
+    }
+}
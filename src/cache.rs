@@ -0,0 +1,227 @@
+//! Local read cache, enabled by the `cache` feature
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{EventEnvelope, WireKVSDatabase, WireKVSError};
+
+pub(crate) struct Cache {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+/// Result of a shared fetch, with `None` for errors that can't be handed to every waiter
+type Outcome = Result<Value, Option<WireKVSError>>;
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<String, Value>,
+    order: VecDeque<String>,
+    generation: u64,
+    // Fetches in progress, which concurrent misses for the same key wait on
+    in_flight: HashMap<String, watch::Receiver<Option<Outcome>>>,
+}
+
+enum Role {
+    /// Sends the request and publishes its outcome
+    Leader(watch::Sender<Option<Outcome>>),
+    /// Waits for the leader's outcome
+    Follower(watch::Receiver<Option<Outcome>>),
+}
+
+/// Removes the in-flight entry of a leader, also when its caller gives up early
+struct Flight<'a> {
+    cache: &'a Cache,
+    key: &'a str,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.cache.state.lock().unwrap().in_flight.remove(self.key);
+    }
+}
+
+impl Cache {
+    /// Creates a cache that stays coherent by listening to `events` until `cancel` fires
+    pub(crate) fn new(capacity: usize, events: broadcast::Receiver<EventEnvelope>, cancel: CancellationToken) -> Arc<Self> {
+        let cache = Arc::new(Cache {
+            capacity,
+            state: Mutex::new(State::default()),
+        });
+        let invalidate = invalidate_on_events(Arc::downgrade(&cache), events);
+        tokio::spawn(async move { cancel.run_until_cancelled(invalidate).await });
+        cache
+    }
+
+    /// Returns the cached value for `key`, or fetches and caches it
+    ///
+    /// Concurrent misses for the same key share one request: the first caller
+    /// becomes the leader and runs its `fetch`, the others wait for its
+    /// outcome. The entry is removed as soon as the leader finishes, so a
+    /// failure is only seen by the callers that waited for it. Followers get
+    /// a copy of the leader's error where the error can be copied, and send
+    /// their own request otherwise. If the leader's caller is dropped before
+    /// it finishes, one of the followers takes over.
+    pub(crate) async fn get_or_fetch<F>(&self, key: &str, fetch: F) -> Result<Value, WireKVSError>
+    where
+        F: Future<Output = Result<Value, WireKVSError>>,
+    {
+        loop {
+            let (generation, role) = {
+                let mut state = self.state.lock().unwrap();
+                if let Some(value) = state.entries.get(key) {
+                    return Ok(value.clone());
+                }
+                let role = match state.in_flight.get(key) {
+                    Some(outcome) => Role::Follower(outcome.clone()),
+                    None => {
+                        let (publish, outcome) = watch::channel(None);
+                        state.in_flight.insert(key.to_string(), outcome);
+                        Role::Leader(publish)
+                    }
+                };
+                (state.generation, role)
+            };
+
+            match role {
+                Role::Leader(publish) => {
+                    let flight = Flight { cache: self, key };
+                    let result = fetch.await;
+                    drop(flight);
+                    if let Ok(value) = &result {
+                        self.insert(key, value.clone(), generation);
+                    }
+                    let outcome = match &result {
+                        Ok(value) => Ok(value.clone()),
+                        Err(e) => Err(e.try_clone()),
+                    };
+                    publish.send_replace(Some(outcome));
+                    return result;
+                }
+                Role::Follower(mut outcome) => {
+                    let shared = match outcome.wait_for(Option::is_some).await {
+                        Ok(outcome) => match &*outcome {
+                            Some(Ok(value)) => Some(Ok(value.clone())),
+                            Some(Err(e)) => e.as_ref().and_then(WireKVSError::try_clone).map(Err),
+                            None => None,
+                        },
+                        // The leader was dropped midway, so take over its fetch
+                        Err(_) => continue,
+                    };
+                    return match shared {
+                        Some(result) => result,
+                        None => fetch.await,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Caches a fetched value unless the cache was invalidated while fetching
+    fn insert(&self, key: &str, value: Value, generation: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        self.put(&mut state, key, value);
+    }
+
+    /// Adds an entry, evicting the oldest ones beyond the capacity
+    fn put(&self, state: &mut State, key: &str, value: Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        if state.entries.insert(key.to_string(), value).is_none() {
+            state.order.push_back(key.to_string());
+        }
+        while state.entries.len() > self.capacity {
+            match state.order.pop_front() {
+                Some(oldest) => {
+                    state.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Generation to pass to `store_written` for a write that is about to be sent
+    pub(crate) fn generation(&self) -> u64 {
+        self.state.lock().unwrap().generation
+    }
+
+    /// Caches a value this handle wrote, or only drops the old one if the
+    /// cache was invalidated while the write was in flight
+    ///
+    /// Bumps the generation like `invalidate`, so reads that started before
+    /// the write can't overwrite the value with what they fetched.
+    pub(crate) fn store_written(&self, key: &str, value: Value, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        let unchanged = state.generation == generation;
+        state.generation += 1;
+        if state.entries.remove(key).is_some() {
+            state.order.retain(|k| k != key);
+        }
+        if unchanged {
+            self.put(&mut state, key, value);
+        }
+    }
+
+    pub(crate) fn invalidate(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        if state.entries.remove(key).is_some() {
+            state.order.retain(|k| k != key);
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.entries.clear();
+        state.order.clear();
+    }
+}
+
+async fn invalidate_on_events(cache: std::sync::Weak<Cache>, mut events: broadcast::Receiver<EventEnvelope>) {
+    loop {
+        let event = events.recv().await;
+        let Some(cache) = cache.upgrade() else {
+            return;
+        };
+        match event {
+            Ok(envelope) => match envelope.event.key() {
+                Some(key) => cache.invalidate(key),
+                None => cache.clear(),
+            },
+            Err(RecvError::Lagged(_)) => cache.clear(),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+impl WireKVSDatabase {
+    /// Drops every locally cached value
+    ///
+    /// Does nothing if the cache is disabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.clear_cache();
+    /// # }
+    /// ```
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Per-key coalescing of events, see `WireKVSDatabase::subscribe_coalesced`
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{Event, EventEnvelope};
+
+/// Spawns the task that buffers `events` for `window` and forwards the latest one per key
+///
+/// The task stops when the receiver is dropped or `cancel` fires.
+pub(crate) fn spawn(
+    events: broadcast::Receiver<EventEnvelope>,
+    window: Duration,
+    capacity: usize,
+    cancel: CancellationToken,
+) -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::channel(capacity);
+    tokio::spawn(async move { cancel.run_until_cancelled(coalesce(events, window, tx)).await });
+    rx
+}
+
+async fn coalesce(mut events: broadcast::Receiver<EventEnvelope>, window: Duration, tx: mpsc::Sender<Event>) {
+    let mut pending = Pending::default();
+    // Set by the first event of a window, so steady traffic can't postpone the flush
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let event = tokio::select! {
+            _ = tx.closed() => return,
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                deadline = None;
+                if !pending.flush(&tx).await {
+                    return;
+                }
+                continue;
+            }
+            event = events.recv() => event.map(|envelope| envelope.event),
+        };
+        match event {
+            Ok(event) => match event.key() {
+                Some(key) => {
+                    let key = key.to_string();
+                    pending.insert(key, event);
+                    deadline.get_or_insert_with(|| Instant::now() + window);
+                }
+                // Connection events go out right away, after everything before them
+                None => {
+                    deadline = None;
+                    if !pending.flush(&tx).await || tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            },
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => {
+                pending.flush(&tx).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Latest event per key, in the order the keys first changed within the window
+#[derive(Default)]
+struct Pending {
+    order: Vec<String>,
+    latest: HashMap<String, Event>,
+}
+
+impl Pending {
+    fn insert(&mut self, key: String, event: Event) {
+        if self.latest.insert(key.clone(), event).is_none() {
+            self.order.push(key);
+        }
+    }
+
+    /// Sends every buffered event, returning `false` once the receiver is gone
+    async fn flush(&mut self, tx: &mpsc::Sender<Event>) -> bool {
+        for key in self.order.drain(..) {
+            if let Some(event) = self.latest.remove(&key) {
+                if tx.send(event).await.is_err() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,481 @@
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{NoProxy, Proxy};
+use url::Url;
+
+use crate::error::WireKVSError;
+use crate::limit::{ConcurrencyLimit, RateLimiter};
+use crate::tls::Certificate;
+
+const DEFAULT_BASE_URL: &str = "https://kvs.wireway.ch/v2";
+const DEFAULT_WS_BASE_URL: &str = "wss://kvs.wireway.ch/events";
+const DEFAULT_USER_AGENT: &str = concat!("wirekvs-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Access settings for a new database, passed to `WireKVS::create_database`
+///
+/// Every permission defaults to `false`.
+///
+/// # Example
+/// ```
+/// use wirekvs::DatabaseConfig;
+///
+/// let config = DatabaseConfig {
+///     allow_public_reads: true,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatabaseConfig {
+    /// Whether anyone can create new keys without the access key
+    pub allow_public_writes: bool,
+    /// Whether anyone can read all entries without the access key
+    pub allow_public_reads: bool,
+    /// Whether anyone can change or delete existing keys without the access key
+    pub allow_public_modifications: bool,
+    /// Whether anyone can read individual keys without the access key
+    pub allow_specific_public_reads: bool,
+}
+
+/// Changes to a database's access settings, passed to `WireKVS::update_database_config`
+///
+/// Permissions left at `None` keep their current value. A full
+/// `DatabaseConfig` converts into an update that sets all four.
+///
+/// # Example
+/// ```
+/// use wirekvs::DatabaseConfigUpdate;
+///
+/// let update = DatabaseConfigUpdate {
+///     allow_public_writes: Some(false),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatabaseConfigUpdate {
+    /// Whether anyone can create new keys without the access key
+    pub allow_public_writes: Option<bool>,
+    /// Whether anyone can read all entries without the access key
+    pub allow_public_reads: Option<bool>,
+    /// Whether anyone can change or delete existing keys without the access key
+    pub allow_public_modifications: Option<bool>,
+    /// Whether anyone can read individual keys without the access key
+    pub allow_specific_public_reads: Option<bool>,
+}
+
+impl DatabaseConfigUpdate {
+    /// The request body, holding only the permissions that change
+    pub(crate) fn to_body(self) -> serde_json::Value {
+        let fields = [
+            ("allowPublicWrites", self.allow_public_writes),
+            ("allowPublicReads", self.allow_public_reads),
+            ("allowPublicModifications", self.allow_public_modifications),
+            ("allowSpecificPublicReads", self.allow_specific_public_reads),
+        ];
+        fields
+            .into_iter()
+            .filter_map(|(field, value)| Some((field.to_string(), value?.into())))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+impl From<DatabaseConfig> for DatabaseConfigUpdate {
+    fn from(config: DatabaseConfig) -> Self {
+        DatabaseConfigUpdate {
+            allow_public_writes: Some(config.allow_public_writes),
+            allow_public_reads: Some(config.allow_public_reads),
+            allow_public_modifications: Some(config.allow_public_modifications),
+            allow_specific_public_reads: Some(config.allow_specific_public_reads),
+        }
+    }
+}
+
+/// Connection settings shared by `WireKVS` and `WireKVSDatabase`
+///
+/// The defaults point at the hosted service. Override them to talk to a
+/// self-hosted deployment, a staging server or a local mock.
+///
+/// # Example
+/// ```
+/// use wirekvs::WireKVSConfig;
+///
+/// let config = WireKVSConfig {
+///     base_url: "http://localhost:8080/v2".to_string(),
+///     ws_base_url: "ws://localhost:8080/events".to_string(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct WireKVSConfig {
+    /// Base URL of the HTTP API
+    ///
+    /// May include a path prefix, such as `https://example.com/kvs/v2` behind
+    /// a reverse proxy; request paths are appended to it. A trailing slash is
+    /// ignored.
+    pub base_url: String,
+    /// Base URL of the event socket, which may include a path prefix like
+    /// `base_url`
+    pub ws_base_url: String,
+    /// Extra query parameters appended to the event socket URL after the
+    /// access key, such as subscription options the server understands
+    ///
+    /// They're sent as given, in order; `accessKey` is reserved.
+    pub ws_query_params: Vec<(String, String)>,
+    /// How the event socket handshake carries the access key
+    pub ws_auth: SocketAuth,
+    /// Subprotocols offered in the `Sec-WebSocket-Protocol` header of the
+    /// event socket handshake, in order of preference; none if empty
+    ///
+    /// When any are offered, the server must pick one of them or the
+    /// connection fails.
+    pub ws_subprotocols: Vec<String>,
+    /// How the event socket reconnects after it drops
+    pub reconnect: ReconnectPolicy,
+    /// Re-reads all entries after every reconnect and emits them as `set`
+    /// events, waiting a random delay of up to this long first; disabled if `None`
+    ///
+    /// Events that fired while the socket was down are lost, so subscribers
+    /// that mirror the database can miss changes. With this enabled, each
+    /// reconnect is followed by a `get_all_entries` call whose entries arrive
+    /// as `{"type": "set"}` events after the `connected` event. Keys deleted
+    /// while disconnected don't produce an event. Keys that change while the
+    /// snapshot is fetched are left out, so their newer event wins. The random
+    /// delay keeps many clients from fetching at once after a server restart;
+    /// the downloaded snapshot costs as much as a `get_all_entries` call.
+    pub resync_on_reconnect: Option<Duration>,
+    /// How often a ping is sent to keep the event socket alive through idle
+    /// proxies and NATs, `Duration::ZERO` disables keepalive
+    pub ping_interval: Duration,
+    /// How long to wait for a pong before the socket is considered dead and
+    /// reconnected
+    pub pong_timeout: Duration,
+    /// Number of events buffered for each subscriber before it starts lagging
+    ///
+    /// A subscriber that falls more than this many events behind skips the
+    /// oldest ones and sees `RecvError::Lagged`. Larger buffers tolerate
+    /// slower consumers and bigger bursts at the cost of memory for every
+    /// buffered event. Must be greater than zero.
+    pub event_buffer_size: usize,
+    /// Maximum number of requests a batch operation keeps in flight at once
+    pub batch_concurrency: usize,
+    /// Time limit for each HTTP request, from connecting until the body is read
+    pub request_timeout: Duration,
+    /// Largest HTTP response body read, in bytes, 64 MiB by default
+    ///
+    /// Bodies are counted while they stream in, and reading stops with
+    /// `WireKVSError::ResponseTooLarge` as soon as one grows past the limit,
+    /// so a huge `get_all_entries` answer can't exhaust memory. Raise it for
+    /// databases whose entries legitimately add up to more. Must be greater
+    /// than zero.
+    pub max_response_size: usize,
+    /// Whether the server wraps values as `{ "value": ..., "version": ..., "updatedAt": ... }`,
+    /// off by default
+    ///
+    /// The hosted API returns values bare. With this set, `get` and the
+    /// other single-key reads unwrap such envelopes and return just the
+    /// value, see `ValueWithMeta` for what counts as one. Leave it off
+    /// otherwise, or stored objects that happen to look like an envelope
+    /// would be unwrapped too. `get_with_metadata` detects envelopes either
+    /// way.
+    pub value_envelopes: bool,
+    /// Most idle HTTP connections kept open per host, unlimited by default
+    ///
+    /// Every database opened from a `WireKVS` shares its connection pool, so
+    /// this bounds the idle sockets to `kvs.wireway.ch` for the whole client,
+    /// not per database. Busy connections are not limited by this.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle HTTP connection is kept before it is closed, 90
+    /// seconds by default and never if `None`
+    pub pool_idle_timeout: Option<Duration>,
+    /// How failed HTTP requests are retried
+    pub retry: RetryPolicy,
+    /// Client-side limit on how fast HTTP requests are sent, unlimited if `None`
+    pub rate_limit: Option<RateLimiter>,
+    /// Client-side cap on concurrent HTTP requests, unlimited if `None`
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+    /// How often read-modify-write helpers like `increment` retry when the
+    /// value changed underneath them
+    pub max_cas_attempts: u32,
+    /// `User-Agent` header sent with every request and the event socket
+    /// handshake, `wirekvs-rust/<crate version>` if `None`
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every HTTP request, e.g. gateway keys or tenant ids
+    ///
+    /// The `Authorization` header set by the crate always takes precedence
+    /// over one listed here. To authenticate differently, pass the desired
+    /// header value as the token or access key, which is sent verbatim.
+    pub default_headers: HeaderMap,
+    /// Proxy for plain `http://` requests and `ws://` event sockets
+    ///
+    /// If neither `http_proxy` nor `https_proxy` is set, the standard
+    /// `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment
+    /// variables are used instead. The event socket can only be tunnelled
+    /// through `http://` proxies, using `CONNECT`.
+    pub http_proxy: Option<String>,
+    /// Proxy for `https://` requests and `wss://` event sockets
+    pub https_proxy: Option<String>,
+    /// Comma-separated hosts that bypass `http_proxy` and `https_proxy`
+    ///
+    /// Entries match the host and its subdomains; `*` disables proxying.
+    pub no_proxy: Option<String>,
+    /// Extra root certificates trusted for HTTPS requests and `wss://` event
+    /// sockets, on top of the system's trust store
+    ///
+    /// Use this for self-hosted deployments behind a private CA. Every
+    /// certificate added here can vouch for any host, so only add CAs you
+    /// control.
+    pub root_certificates: Vec<Certificate>,
+    /// Accepts any server certificate, including expired, self-signed and
+    /// mismatched ones
+    ///
+    /// **This disables TLS authentication entirely.** Anyone on the network
+    /// path can then impersonate the server and read the token, access keys
+    /// and data. Only enable it against a local test server, never in
+    /// production.
+    pub danger_accept_invalid_certs: bool,
+    /// Number of `get` results kept in the local cache, disabled if `None`
+    ///
+    /// Cached values are dropped when this handle writes the key and when the
+    /// event socket reports a change to it; the whole cache is cleared after a
+    /// reconnect. Writes by other clients are only noticed once their event
+    /// arrives, so the cache is eventually consistent, bounded by event
+    /// latency. When full, the oldest cached entry is evicted first.
+    ///
+    /// Concurrent `get` misses for the same key share a single request. A
+    /// failed request isn't cached, so the next `get` tries again. `Some(0)`
+    /// keeps the request sharing without caching any values.
+    #[cfg(feature = "cache")]
+    pub cache_capacity: Option<usize>,
+    /// Puts values written through `set` into the local cache instead of only
+    /// dropping the cached copy, so a following `get` can return them without
+    /// a request
+    ///
+    /// Needs `cache_capacity`. The written value stays cached until any event
+    /// for the key arrives, usually the server echoing this very write, which
+    /// drops it so the next `get` reads whatever the server reports; the
+    /// server's value always wins. If the cache was invalidated while the
+    /// write was in flight, for example by an event, the value isn't cached
+    /// at all, since it may already be outdated. Other clients' reads are
+    /// unaffected.
+    #[cfg(feature = "cache")]
+    pub read_your_writes: bool,
+}
+
+impl Default for WireKVSConfig {
+    fn default() -> Self {
+        WireKVSConfig {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            ws_base_url: DEFAULT_WS_BASE_URL.to_string(),
+            ws_query_params: Vec::new(),
+            ws_auth: SocketAuth::default(),
+            ws_subprotocols: Vec::new(),
+            reconnect: ReconnectPolicy::default(),
+            resync_on_reconnect: None,
+            ping_interval: Duration::from_secs(30),
+            pong_timeout: Duration::from_secs(10),
+            event_buffer_size: 100,
+            batch_concurrency: 8,
+            request_timeout: Duration::from_secs(30),
+            max_response_size: 64 * 1024 * 1024,
+            value_envelopes: false,
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            concurrency_limit: None,
+            max_cas_attempts: 10,
+            user_agent: None,
+            default_headers: HeaderMap::new(),
+            http_proxy: None,
+            https_proxy: None,
+            no_proxy: None,
+            root_certificates: Vec::new(),
+            danger_accept_invalid_certs: false,
+            #[cfg(feature = "cache")]
+            cache_capacity: None,
+            #[cfg(feature = "cache")]
+            read_your_writes: false,
+        }
+    }
+}
+
+impl WireKVSConfig {
+    /// Builds the HTTP client shared by every request made with this config
+    ///
+    /// Fails with `WireKVSError::InvalidConfig` if reqwest rejects a proxy,
+    /// a certificate or the TLS setup.
+    pub(crate) fn http_client(&self) -> Result<reqwest::Client, WireKVSError> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.request_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .default_headers(self.default_headers.clone())
+            .user_agent(self.user_agent());
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.for_http()?);
+        }
+        builder = builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+        let no_proxy = || self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let invalid_proxy = |proxy: &str, e: reqwest::Error| {
+            WireKVSError::InvalidConfig(format!("invalid proxy {:?}: {}", proxy, e))
+        };
+        if let Some(proxy) = &self.http_proxy {
+            builder = builder.proxy(Proxy::http(proxy).map_err(|e| invalid_proxy(proxy, e))?.no_proxy(no_proxy()));
+        }
+        if let Some(proxy) = &self.https_proxy {
+            builder = builder.proxy(Proxy::https(proxy).map_err(|e| invalid_proxy(proxy, e))?.no_proxy(no_proxy()));
+        }
+        builder
+            .build()
+            .map_err(|e| WireKVSError::InvalidConfig(format!("failed to build HTTP client: {}", e)))
+    }
+
+    /// The `User-Agent` to send, falling back to the crate's own
+    pub(crate) fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    /// Checks the settings a builder can't express through types alone
+    pub(crate) fn validate(&self) -> Result<(), WireKVSError> {
+        let proxies = [&self.http_proxy, &self.https_proxy].into_iter().flatten();
+        for url in [&self.base_url, &self.ws_base_url].into_iter().chain(proxies) {
+            if let Err(e) = Url::parse(url) {
+                return Err(WireKVSError::InvalidConfig(format!("invalid url {:?}: {}", url, e)));
+            }
+        }
+        if self.ws_query_params.iter().any(|(name, _)| name == "accessKey") {
+            return Err(WireKVSError::InvalidConfig("accessKey can't be set as a socket query parameter".to_string()));
+        }
+        for protocol in &self.ws_subprotocols {
+            // Protocols are joined with commas into a single header value
+            if protocol.is_empty() || protocol.contains(|c: char| c == ',' || !c.is_ascii_graphic()) {
+                return Err(WireKVSError::InvalidConfig(format!("invalid socket subprotocol {:?}", protocol)));
+            }
+        }
+        if self.request_timeout.is_zero() {
+            return Err(WireKVSError::InvalidConfig("request timeout must be greater than zero".to_string()));
+        }
+        if self.max_response_size == 0 {
+            return Err(WireKVSError::InvalidConfig("max response size must be greater than zero".to_string()));
+        }
+        if HeaderValue::from_str(self.user_agent()).is_err() {
+            return Err(WireKVSError::InvalidConfig(format!("invalid user agent {:?}", self.user_agent())));
+        }
+        if self.event_buffer_size == 0 {
+            return Err(WireKVSError::InvalidConfig("event buffer size must be greater than zero".to_string()));
+        }
+        if let Some(limiter) = &self.rate_limit {
+            limiter.validate()?;
+        }
+        if let Some(limit) = &self.concurrency_limit {
+            limit.validate()?;
+        }
+        for certificate in &self.root_certificates {
+            certificate.for_http()?;
+        }
+        Ok(())
+    }
+}
+
+/// Exponential backoff settings for event socket reconnects
+///
+/// The delay before attempt `n` (starting at zero) is `base_backoff * 2^n`,
+/// capped at `max_backoff`. Set `max_retries` to zero to disable reconnecting.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Number of reconnect attempts before giving up
+    pub max_retries: u32,
+    /// Delay before the first reconnect attempt
+    pub base_backoff: Duration,
+    /// Upper bound for the delay between attempts
+    pub max_backoff: Duration,
+}
+
+impl ReconnectPolicy {
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        exponential(self.base_backoff, self.max_backoff, attempt)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_retries: 10,
+            base_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Where the access key goes in the event socket handshake
+///
+/// The hosted API expects it in the URL, which is the default. URLs tend to
+/// end up in proxy and server logs, so prefer `Header` for deployments that
+/// accept it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SocketAuth {
+    /// As the `accessKey` query parameter
+    #[default]
+    QueryParam,
+    /// As the `Authorization` header of the upgrade request, the same way
+    /// HTTP requests send it
+    Header,
+}
+
+/// Retry settings for HTTP requests
+///
+/// Idempotent operations (`get`, `get_all_entries`, `delete`,
+/// `list_databases`, `delete_database`) are retried on network errors and on
+/// 502, 503 and 504 responses. Writes such as `set` are only retried when
+/// `retry_non_idempotent` is enabled, since a retried write may be applied
+/// twice. `set_idempotent` is retried like a read, since its idempotency key
+/// lets the server drop the duplicates.
+///
+/// A 429 response is retried under the same rules after the delay given in
+/// its `Retry-After` header. When that delay exceeds `max_delay`, or the
+/// attempts run out, the request fails with `WireKVSError::RateLimited`.
+///
+/// The delay before retry `n` (starting at zero) is `base_delay * 2^n`,
+/// capped at `max_delay`, with random jitter of up to half the delay.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts per request, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Whether non-idempotent writes are retried as well
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn disabled() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = exponential(self.base_delay, self.max_delay, attempt);
+        delay - delay.mul_f64(rand::random::<f64>() / 2.0)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+fn exponential(base: Duration, max: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt)).min(max)
+}
@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::config::DatabaseConfig;
+use crate::error::{decode_error, from_json, WireKVSError};
+
+/// A database returned by `WireKVS::create_database`
+///
+/// Pass it to `WireKVS::connect` to open a handle without copying the
+/// credentials out by hand.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CreatedDatabase {
+    /// Id of the new database
+    #[serde(rename = "kvsId")]
+    pub id: String,
+    /// Access key for direct operations on the new database
+    #[serde(rename = "accessKey")]
+    pub access_key: String,
+    /// Display name of the new database
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Current settings and metadata of a database, see `WireKVS::get_database_info`
+///
+/// Fields the server leaves out fall back to defaults: an empty `name`, no
+/// `created_at`, and `false` for each permission, matching what
+/// `create_database` assumes when a flag isn't set. A body with none of
+/// these fields isn't a database description and fails to decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseInfo {
+    /// Id of the database
+    pub id: String,
+    /// Display name of the database
+    pub name: String,
+    /// Public access permissions
+    pub config: DatabaseConfig,
+    /// When the database was created, as sent by the server, see `created_at_utc`
+    pub created_at: Option<String>,
+}
+
+impl DatabaseInfo {
+    /// Reads the database detail body, using `id` if the body doesn't repeat it
+    pub(crate) fn from_value(id: &str, body: Value) -> Result<DatabaseInfo, WireKVSError> {
+        let metadata = ["name", "createdAt"].iter().any(|field| body.get(field).is_some());
+        if !metadata && !Self::describes_config(&body) {
+            let source = serde::de::Error::custom("expected at least one database info field");
+            return Err(decode_error(source, body.to_string().as_bytes()));
+        }
+        let map: Map<String, Value> = from_json(body)?;
+        let text = |field: &str| map.get(field).and_then(Value::as_str).map(str::to_string);
+        let flag = |field: &str| map.get(field).and_then(Value::as_bool).unwrap_or(false);
+        Ok(DatabaseInfo {
+            id: text("kvsId").or_else(|| text("id")).unwrap_or_else(|| id.to_string()),
+            name: text("name").unwrap_or_default(),
+            config: DatabaseConfig {
+                allow_public_writes: flag("allowPublicWrites"),
+                allow_public_reads: flag("allowPublicReads"),
+                allow_public_modifications: flag("allowPublicModifications"),
+                allow_specific_public_reads: flag("allowSpecificPublicReads"),
+            },
+            created_at: map.get("createdAt").and_then(crate::timestamp::text),
+        })
+    }
+
+    /// `created_at` parsed from RFC 3339 or epoch milliseconds, `None` if missing or unparseable
+    #[cfg(feature = "chrono")]
+    pub fn created_at_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.created_at.as_deref().and_then(crate::timestamp::parse)
+    }
+
+    /// Whether `body` carries any permission flag, as opposed to a bare acknowledgement
+    pub(crate) fn describes_config(body: &Value) -> bool {
+        ["allowPublicWrites", "allowPublicReads", "allowPublicModifications", "allowSpecificPublicReads"]
+            .iter()
+            .any(|field| body.get(field).is_some_and(Value::is_boolean))
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite;
+
+/// Errors returned by WireKVS operations
+#[derive(Debug)]
+pub enum WireKVSError {
+    /// The request could not be sent or the response could not be read
+    Network(reqwest::Error),
+    /// The server rejected the token or access key (HTTP 401 or 403)
+    Unauthorized,
+    /// The requested key or database does not exist (HTTP 404)
+    NotFound,
+    /// The server answered with any other non-success status
+    Api { status: u16, message: String },
+    /// An empty key, or a key of `.` or `..`, was passed to a key operation
+    InvalidKey,
+    /// The request did not complete within `WireKVSConfig::request_timeout`
+    Timeout,
+    /// The response body was not the JSON an operation expected; `body_snippet`
+    /// holds its first 256 bytes, or `None` if the JSON wasn't a response
+    Decode {
+        source: serde_json::Error,
+        body_snippet: Option<String>,
+    },
+    /// A client was configured with invalid settings
+    InvalidConfig(String),
+    /// A request or event socket URL could not be built
+    InvalidUrl(url::ParseError),
+    /// The event socket could not be established or failed
+    WebSocket(Box<tungstenite::Error>),
+    /// A stored value doesn't have the JSON type an operation needs
+    TypeMismatch { expected: &'static str, found: &'static str },
+    /// An optimistic update kept losing against concurrent writers and gave up
+    Conflict { attempts: u32 },
+    /// Some operations of a batch failed, listed with the key each one was for
+    Batch { failures: Vec<(String, WireKVSError)> },
+    /// The request was held back by the client's rate limiter or rejected with
+    /// HTTP 429; try again after `retry_after`
+    RateLimited { retry_after: Duration },
+    /// The server doesn't implement `operation` (HTTP 405 or 501)
+    Unsupported { operation: &'static str },
+    /// A message couldn't be sent because the event socket isn't connected
+    NotConnected,
+    /// A write that must not replace an existing value found `key` already set
+    AlreadyExists { key: String },
+    /// The response body was larger than `WireKVSConfig::max_response_size`
+    /// and was not read any further
+    ResponseTooLarge { limit: usize },
+}
+
+impl WireKVSError {
+    /// Stable snake_case name of the variant, such as `"not_found"` or `"rate_limited"`
+    ///
+    /// Unlike the `Display` output, these names are not reworded between
+    /// releases, so they are safe to match on in logs, metrics and clients.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WireKVSError::Network(_) => "network",
+            WireKVSError::Unauthorized => "unauthorized",
+            WireKVSError::NotFound => "not_found",
+            WireKVSError::Api { .. } => "api",
+            WireKVSError::InvalidKey => "invalid_key",
+            WireKVSError::Timeout => "timeout",
+            WireKVSError::Decode { .. } => "decode",
+            WireKVSError::InvalidConfig(_) => "invalid_config",
+            WireKVSError::InvalidUrl(_) => "invalid_url",
+            WireKVSError::WebSocket(_) => "websocket",
+            WireKVSError::TypeMismatch { .. } => "type_mismatch",
+            WireKVSError::Conflict { .. } => "conflict",
+            WireKVSError::Batch { .. } => "batch",
+            WireKVSError::RateLimited { .. } => "rate_limited",
+            WireKVSError::Unsupported { .. } => "unsupported",
+            WireKVSError::NotConnected => "not_connected",
+            WireKVSError::AlreadyExists { .. } => "already_exists",
+            WireKVSError::ResponseTooLarge { .. } => "response_too_large",
+        }
+    }
+}
+
+impl WireKVSError {
+    /// Copies the error, or `None` for variants that wrap errors which can't be cloned
+    #[cfg(feature = "cache")]
+    pub(crate) fn try_clone(&self) -> Option<WireKVSError> {
+        Some(match self {
+            WireKVSError::Network(_) | WireKVSError::Decode { .. } | WireKVSError::WebSocket(_) => return None,
+            WireKVSError::Batch { .. } => return None,
+            WireKVSError::Unauthorized => WireKVSError::Unauthorized,
+            WireKVSError::NotFound => WireKVSError::NotFound,
+            WireKVSError::Api { status, message } => WireKVSError::Api {
+                status: *status,
+                message: message.clone(),
+            },
+            WireKVSError::InvalidKey => WireKVSError::InvalidKey,
+            WireKVSError::Timeout => WireKVSError::Timeout,
+            WireKVSError::InvalidConfig(message) => WireKVSError::InvalidConfig(message.clone()),
+            WireKVSError::InvalidUrl(e) => WireKVSError::InvalidUrl(*e),
+            WireKVSError::TypeMismatch { expected, found } => WireKVSError::TypeMismatch { expected, found },
+            WireKVSError::Conflict { attempts } => WireKVSError::Conflict { attempts: *attempts },
+            WireKVSError::RateLimited { retry_after } => WireKVSError::RateLimited {
+                retry_after: *retry_after,
+            },
+            WireKVSError::Unsupported { operation } => WireKVSError::Unsupported { operation },
+            WireKVSError::NotConnected => WireKVSError::NotConnected,
+            WireKVSError::AlreadyExists { key } => WireKVSError::AlreadyExists { key: key.clone() },
+            WireKVSError::ResponseTooLarge { limit } => WireKVSError::ResponseTooLarge { limit: *limit },
+        })
+    }
+}
+
+impl fmt::Display for WireKVSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireKVSError::Network(e) => write!(f, "network error: {}", e),
+            WireKVSError::Unauthorized => write!(f, "unauthorized: invalid token or access key"),
+            WireKVSError::NotFound => write!(f, "not found"),
+            WireKVSError::Api { status, message } => write!(f, "api error ({}): {}", status, message),
+            WireKVSError::InvalidKey => write!(f, "invalid key: keys must not be empty, \".\" or \"..\""),
+            WireKVSError::Timeout => write!(f, "request timed out"),
+            WireKVSError::Decode { source, body_snippet } => {
+                write!(f, "failed to decode response: {}", source)?;
+                match body_snippet {
+                    Some(snippet) => write!(f, "; body starts with {:?}", snippet),
+                    None => Ok(()),
+                }
+            }
+            WireKVSError::InvalidConfig(message) => write!(f, "invalid config: {}", message),
+            WireKVSError::InvalidUrl(e) => write!(f, "invalid url: {}", e),
+            WireKVSError::WebSocket(e) => write!(f, "websocket error: {}", e),
+            WireKVSError::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch: expected {}, found {}", expected, found)
+            }
+            WireKVSError::Conflict { attempts } => {
+                write!(f, "conflict: value kept changing, gave up after {} attempt(s)", attempts)
+            }
+            WireKVSError::Batch { failures } => {
+                write!(f, "{} batch operation(s) failed:", failures.len())?;
+                for (key, e) in failures {
+                    write!(f, " [{}: {}]", key, e)?;
+                }
+                Ok(())
+            }
+            WireKVSError::RateLimited { retry_after } => {
+                write!(f, "rate limited: retry after {:?}", retry_after)
+            }
+            WireKVSError::Unsupported { operation } => write!(f, "unsupported by the server: {}", operation),
+            WireKVSError::NotConnected => write!(f, "event socket is not connected"),
+            WireKVSError::AlreadyExists { key } => write!(f, "key already exists: {}", key),
+            WireKVSError::ResponseTooLarge { limit } => {
+                write!(f, "response body exceeds the limit of {} bytes", limit)
+            }
+        }
+    }
+}
+
+/// Serializes as an object with the variant's `kind`, the `Display` text as
+/// `message`, and the variant's data where it has any:
+///
+/// | `kind` | extra fields |
+/// |---|---|
+/// | `api` | `status`: number |
+/// | `type_mismatch` | `expected`, `found`: JSON type names |
+/// | `conflict` | `attempts`: number |
+/// | `batch` | `failures`: array of `{"key": string, "error": object}` |
+/// | `rate_limited` | `retry_after_ms`: number |
+/// | `unsupported` | `operation`: string |
+/// | `already_exists` | `key`: string |
+/// | `response_too_large` | `limit`: number of bytes |
+/// | `decode` | `body_snippet`: string, if known |
+///
+/// Wrapped `reqwest`, `serde_json`, `url` and WebSocket errors only appear
+/// through `message`.
+///
+/// ```json
+/// {"kind": "api", "message": "api error (400): value too large", "status": 400}
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for WireKVSError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            WireKVSError::Api { status, .. } => map.serialize_entry("status", status)?,
+            WireKVSError::TypeMismatch { expected, found } => {
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("found", found)?;
+            }
+            WireKVSError::Conflict { attempts } => map.serialize_entry("attempts", attempts)?,
+            WireKVSError::Batch { failures } => {
+                let failures: Vec<_> = failures
+                    .iter()
+                    .map(|(key, error)| serde_json::json!({ "key": key, "error": error }))
+                    .collect();
+                map.serialize_entry("failures", &failures)?;
+            }
+            WireKVSError::RateLimited { retry_after } => {
+                map.serialize_entry("retry_after_ms", &(retry_after.as_millis() as u64))?;
+            }
+            WireKVSError::Unsupported { operation } => map.serialize_entry("operation", operation)?,
+            WireKVSError::AlreadyExists { key } => map.serialize_entry("key", key)?,
+            WireKVSError::ResponseTooLarge { limit } => map.serialize_entry("limit", limit)?,
+            WireKVSError::Decode {
+                body_snippet: Some(snippet),
+                ..
+            } => map.serialize_entry("body_snippet", snippet)?,
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+impl std::error::Error for WireKVSError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WireKVSError::Network(e) => Some(e),
+            WireKVSError::Decode { source, .. } => Some(source),
+            WireKVSError::InvalidUrl(e) => Some(e),
+            WireKVSError::WebSocket(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for WireKVSError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return WireKVSError::Timeout;
+        }
+        WireKVSError::Network(e)
+    }
+}
+
+impl From<serde_json::Error> for WireKVSError {
+    fn from(e: serde_json::Error) -> Self {
+        WireKVSError::Decode {
+            source: e,
+            body_snippet: None,
+        }
+    }
+}
+
+impl From<url::ParseError> for WireKVSError {
+    fn from(e: url::ParseError) -> Self {
+        WireKVSError::InvalidUrl(e)
+    }
+}
+
+impl From<tungstenite::Error> for WireKVSError {
+    fn from(e: tungstenite::Error) -> Self {
+        WireKVSError::WebSocket(Box::new(e))
+    }
+}
+
+/// Names the JSON type of `value` for `TypeMismatch` errors
+pub(crate) fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "float",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Converts a fetched value with `convert`, reporting a `TypeMismatch` if it returns `None`
+pub(crate) fn expect_type<T>(
+    value: Option<Value>,
+    expected: &'static str,
+    convert: impl FnOnce(Value) -> Option<T>,
+) -> Result<Option<T>, WireKVSError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let found = json_type(&value);
+    convert(value).map(Some).ok_or(WireKVSError::TypeMismatch { expected, found })
+}
+
+/// Turns the collected failures of a batch into a single result
+pub(crate) fn batch_result(failures: Vec<(String, WireKVSError)>) -> Result<(), WireKVSError> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(WireKVSError::Batch { failures })
+    }
+}
+
+/// Wait reported for a 429 response that doesn't say how long to back off
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Reads a `Retry-After` header given either as seconds or as an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Maps non-2xx responses into the matching `WireKVSError` variant
+pub(crate) async fn check_status(response: Response) -> Result<Response, WireKVSError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(WireKVSError::Unauthorized),
+        StatusCode::NOT_FOUND => Err(WireKVSError::NotFound),
+        StatusCode::TOO_MANY_REQUESTS => Err(WireKVSError::RateLimited {
+            retry_after: retry_after(response.headers()).unwrap_or(DEFAULT_RETRY_AFTER),
+        }),
+        _ => {
+            let body = match read_body(response, ERROR_BODY_LIMIT).await {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(_) => String::new(),
+            };
+            Err(WireKVSError::Api {
+                status: status.as_u16(),
+                message: error_message(&body),
+            })
+        }
+    }
+}
+
+/// Most bytes of an error body read for `WireKVSError::Api` messages
+const ERROR_BODY_LIMIT: usize = 64 * 1024;
+
+/// Reads the body chunk by chunk, giving up once it grows past `limit` bytes
+///
+/// A `Content-Length` over the limit fails before anything is read; bodies
+/// without one are counted as they stream in, so at most `limit` bytes plus
+/// one chunk are ever held in memory.
+pub(crate) async fn read_body(mut response: Response, limit: usize) -> Result<Vec<u8>, WireKVSError> {
+    if response.content_length().is_some_and(|length| length > limit as u64) {
+        return Err(WireKVSError::ResponseTooLarge { limit });
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(WireKVSError::ResponseTooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Checks the status and decodes a body of at most `limit` bytes as JSON
+pub(crate) async fn parse_json(response: Response, limit: usize) -> Result<Value, WireKVSError> {
+    let bytes = read_body(check_status(response).await?, limit).await?;
+    serde_json::from_slice(&bytes).map_err(|e| decode_error(e, &bytes))
+}
+
+/// Deserializes a JSON value received from the server into `T`
+///
+/// Unlike `serde_json::from_value`, a failure keeps the start of the value
+/// as the error's `body_snippet`.
+pub(crate) fn from_json<T: DeserializeOwned>(value: Value) -> Result<T, WireKVSError> {
+    T::deserialize(&value).map_err(|e| decode_error(e, value.to_string().as_bytes()))
+}
+
+/// Most bytes of a body kept in `WireKVSError::Decode::body_snippet`
+const DECODE_SNIPPET_LIMIT: usize = 256;
+
+/// Builds a `Decode` error carrying the start of the body that failed to decode
+pub(crate) fn decode_error(source: serde_json::Error, body: &[u8]) -> WireKVSError {
+    let mut end = body.len().min(DECODE_SNIPPET_LIMIT);
+    // Don't cut a multi-byte character in half
+    while end < body.len() && end > 0 && (body[end] & 0xC0) == 0x80 {
+        end -= 1;
+    }
+    let mut snippet = String::from_utf8_lossy(&body[..end]).into_owned();
+    if end < body.len() {
+        snippet.push_str("...");
+    }
+    WireKVSError::Decode {
+        source,
+        body_snippet: Some(snippet),
+    }
+}
+
+/// Pulls a human readable message out of an error body, falling back to the raw text
+fn error_message(body: &str) -> String {
+    if let Ok(Value::Object(map)) = serde_json::from_str::<Value>(body) {
+        for field in ["error", "message"] {
+            if let Some(Value::String(message)) = map.get(field) {
+                return message.clone();
+            }
+        }
+    }
+    body.trim().to_string()
+}
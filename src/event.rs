@@ -0,0 +1,245 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+/// A change notification received from the event socket
+///
+/// Events are parsed from the `type` field of each frame:
+///
+/// | `type`                  | Variant        | Other fields           |
+/// |-------------------------|----------------|------------------------|
+/// | `"set"` / `"update"`    | `Set`          | `key`, `value`         |
+/// | `"delete"` / `"deleted"`| `Deleted`      | `key`                  |
+/// | `"expire"` / `"expired"`| `Expired`      | `key`                  |
+/// | `"connected"`           | `Connected`    |                        |
+/// | `"disconnected"`        | `Disconnected` |                        |
+///
+/// `Connected` and `Disconnected` are emitted by the client itself when the
+/// socket reconnects or drops. Frames that don't match any of the above are
+/// kept as `Unknown` so new server events are never silently dropped.
+///
+/// `Expired` is only seen if the server reports expiry as its own event type.
+/// Servers that announce an expired key like any other delete produce
+/// `Deleted` instead, so consumers that only care that a key is gone should
+/// handle both.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A key was created or updated
+    Set { key: String, value: Value },
+    /// A key was deleted
+    Deleted { key: String },
+    /// A key reached the end of its TTL and was removed by the server
+    Expired { key: String },
+    /// The event socket (re)connected
+    Connected,
+    /// The event socket dropped
+    Disconnected,
+    /// Any event this client doesn't model yet
+    Unknown(Value),
+}
+
+impl Event {
+    /// Parses a raw event frame
+    ///
+    /// # Example
+    /// ```
+    /// use serde_json::json;
+    /// use wirekvs::Event;
+    ///
+    /// let event = Event::from_value(&json!({ "type": "delete", "key": "greeting" }));
+    /// assert_eq!(event, Event::Deleted { key: "greeting".to_string() });
+    /// ```
+    pub fn from_value(raw: &Value) -> Event {
+        let key = raw.get("key").and_then(Value::as_str).map(str::to_string);
+        match (raw.get("type").and_then(Value::as_str), key) {
+            (Some("set" | "update"), Some(key)) => Event::Set {
+                key,
+                value: raw.get("value").cloned().unwrap_or(Value::Null),
+            },
+            (Some("delete" | "deleted"), Some(key)) => Event::Deleted { key },
+            (Some("expire" | "expired"), Some(key)) => Event::Expired { key },
+            (Some("connected"), _) => Event::Connected,
+            (Some("disconnected"), _) => Event::Disconnected,
+            _ => Event::Unknown(raw.clone()),
+        }
+    }
+
+    /// Returns the key this event refers to, if any
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Event::Set { key, .. } | Event::Deleted { key } | Event::Expired { key } => Some(key),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed event together with the frame it was parsed from, as received
+/// from `WireKVSDatabase::subscribe_typed`
+///
+/// Gives access to fields `Event` doesn't model, such as metadata a newer
+/// server adds to its frames. The frame is shared between all receivers
+/// instead of being copied for each one.
+///
+/// # Example
+/// ```
+/// use serde_json::json;
+/// use wirekvs::{Event, EventEnvelope};
+///
+/// let envelope = EventEnvelope::from_value(json!({ "type": "delete", "key": "greeting", "by": "admin" }));
+/// assert_eq!(envelope.event, Event::Deleted { key: "greeting".to_string() });
+/// assert_eq!(envelope.raw["by"], "admin");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventEnvelope {
+    /// The parsed event
+    pub event: Event,
+    /// The frame as received
+    pub raw: Arc<Value>,
+}
+
+impl EventEnvelope {
+    /// Parses a raw event frame, keeping the frame alongside
+    pub fn from_value(raw: Value) -> EventEnvelope {
+        EventEnvelope {
+            event: Event::from_value(&raw),
+            raw: Arc::new(raw),
+        }
+    }
+
+    /// The frame's `timestamp` field parsed from RFC 3339 or epoch
+    /// milliseconds, `None` if the server doesn't send one
+    #[cfg(feature = "chrono")]
+    pub fn timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        crate::timestamp::text(self.raw.get("timestamp")?).as_deref().and_then(crate::timestamp::parse)
+    }
+}
+
+/// Outcome of `WireKVSDatabase::graceful_shutdown`
+///
+/// Events are counted once however many subscribers they were buffered for.
+/// An event counts as dropped if at least one subscriber hadn't received it
+/// when the drain timed out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Buffered events every subscriber received before the socket closed
+    pub delivered: usize,
+    /// Buffered events that some subscriber never received
+    pub dropped: usize,
+}
+
+/// Formats a raw event for logging, indented over several lines if `pretty`
+///
+/// # Example
+/// ```
+/// use serde_json::json;
+/// use wirekvs::format_event;
+///
+/// let event = json!({ "type": "set", "key": "greeting", "value": "Hello!" });
+/// assert_eq!(format_event(&event, false), r#"{"key":"greeting","type":"set","value":"Hello!"}"#);
+/// ```
+pub fn format_event(event: &Value, pretty: bool) -> String {
+    if pretty {
+        format!("{:#}", event)
+    } else {
+        event.to_string()
+    }
+}
+
+/// Fans every incoming event out to the raw and typed channels
+///
+/// One receiver per channel is created up front and handed to the first
+/// subscriber of that channel, so events that arrive between the socket
+/// connecting and the first `subscribe` call are still delivered. Once such a
+/// receiver's buffer is full it is dropped rather than left to lag, so a late
+/// first subscriber starts at the live position instead of with
+/// `RecvError::Lagged` and a backlog of stale events.
+#[derive(Clone)]
+pub(crate) struct Broadcaster {
+    capacity: usize,
+    raw: broadcast::Sender<Value>,
+    typed: broadcast::Sender<EventEnvelope>,
+    first_raw: Arc<Mutex<Option<broadcast::Receiver<Value>>>>,
+    first_typed: Arc<Mutex<Option<broadcast::Receiver<EventEnvelope>>>>,
+    // Set by `seal`, after which incoming events are discarded
+    sealed: Arc<AtomicBool>,
+}
+
+impl Broadcaster {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let (raw, first_raw) = broadcast::channel(capacity);
+        let (typed, first_typed) = broadcast::channel(capacity);
+        Broadcaster {
+            capacity,
+            raw,
+            typed,
+            first_raw: Arc::new(Mutex::new(Some(first_raw))),
+            first_typed: Arc::new(Mutex::new(Some(first_typed))),
+            sealed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn send(&self, raw: Value) {
+        if self.sealed.load(Ordering::Acquire) {
+            return;
+        }
+        release_if_full(&self.first_raw, self.capacity);
+        release_if_full(&self.first_typed, self.capacity);
+        if self.typed.receiver_count() > 0 {
+            let _ = self.typed.send(EventEnvelope::from_value(raw.clone()));
+        }
+        let _ = self.raw.send(raw);
+    }
+
+    pub(crate) fn subscribe_raw(&self) -> broadcast::Receiver<Value> {
+        self.first_raw.lock().unwrap().take().unwrap_or_else(|| self.raw.subscribe())
+    }
+
+    pub(crate) fn subscribe_typed(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.first_typed.lock().unwrap().take().unwrap_or_else(|| self.typed.subscribe())
+    }
+
+    /// Stops forwarding events, so the buffers only drain from here on
+    ///
+    /// Drops the first receivers nobody claimed, which would otherwise hold
+    /// on to every event forever.
+    pub(crate) fn seal(&self) {
+        self.sealed.store(true, Ordering::Release);
+        self.first_raw.lock().unwrap().take();
+        self.first_typed.lock().unwrap().take();
+    }
+
+    /// Number of events some receiver hasn't received yet, on the channel
+    /// furthest behind
+    pub(crate) fn pending(&self) -> usize {
+        self.raw.len().max(self.typed.len())
+    }
+
+    /// Number of live receivers across all channels, not counting the
+    /// first receivers nobody has claimed yet
+    pub(crate) fn receiver_count(&self) -> usize {
+        let unclaimed = [
+            self.first_raw.lock().unwrap().is_some(),
+            self.first_typed.lock().unwrap().is_some(),
+        ]
+        .into_iter()
+        .filter(|unclaimed| *unclaimed)
+        .count();
+        self.raw.receiver_count() + self.typed.receiver_count() - unclaimed
+    }
+
+    /// Receiver for the crate's own consumers, which never take the first receiver
+    pub(crate) fn subscribe_internal(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.typed.subscribe()
+    }
+}
+
+/// Drops an unclaimed first receiver whose buffer is full, before the next
+/// event would make it lag
+fn release_if_full<T: Clone>(first: &Mutex<Option<broadcast::Receiver<T>>>, capacity: usize) {
+    let mut first = first.lock().unwrap();
+    if first.as_ref().is_some_and(|receiver| receiver.len() >= capacity) {
+        *first = None;
+    }
+}
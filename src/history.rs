@@ -0,0 +1,142 @@
+//! Version history, enabled by the `history` feature
+//!
+//! The public WireKVS API doesn't document version history, so these methods
+//! only work against deployments that keep it. They assume the server returns
+//! an old revision of a key for a `version` query parameter, and lists the
+//! revisions of a key at `/database/{id}/{key}/history` as an array of
+//! `{ "version": n, "value": ..., "updatedAt": "..." }` objects.
+//!
+//! Conditional deletes send the expected revision as an `If-Match` entity tag
+//! and expect HTTP 412 when the key has moved on.
+//!
+//! Servers without history answer these requests with HTTP 404, 405 or 501.
+//! 405 and 501 are reported as `WireKVSError::Unsupported`. A 404 is still
+//! `WireKVSError::NotFound`, because it can't be told apart from a missing
+//! key or revision.
+
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{check_status, from_json, parse_json};
+use crate::{metrics, WireKVSDatabase, WireKVSError};
+
+/// One stored revision of a key, see `WireKVSDatabase::history`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct VersionedValue {
+    /// Revision number, increasing with every write to the key
+    pub version: u64,
+    /// The value as it was at this revision
+    #[serde(default)]
+    pub value: Value,
+    /// When this revision was written, as sent by the server, see `updated_at_utc`
+    #[serde(default, rename = "updatedAt", deserialize_with = "crate::timestamp::deserialize_text")]
+    pub updated_at: Option<String>,
+}
+
+impl VersionedValue {
+    /// `updated_at` parsed from RFC 3339 or epoch milliseconds, `None` if missing or unparseable
+    #[cfg(feature = "chrono")]
+    pub fn updated_at_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.updated_at.as_deref().and_then(crate::timestamp::parse)
+    }
+}
+
+impl WireKVSDatabase {
+    /// Gets the value `key` had at revision `version`
+    ///
+    /// Revision numbers are the ones reported by `history` and
+    /// `SetResponse::version`. Fails with `WireKVSError::NotFound` if the key
+    /// or the revision doesn't exist, including revisions the server has
+    /// already pruned.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let original = db.get_version("config", 1).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn get_version(&self, key: &str, version: u64) -> Result<Value, WireKVSError> {
+        let request = self
+            .client
+            .get(self.key_url(key)?)
+            .query(&[("version", version)]);
+        metrics::observe("get_version", async {
+            let response = self.send(true, request).await?;
+            parse_json(supported(response, "get_version")?, self.config.max_response_size).await
+        })
+        .await
+    }
+
+    /// Lists the stored revisions of `key`, oldest first
+    ///
+    /// The list holds whatever the server retains, which may stop short of
+    /// the first write. A key that was never written fails with
+    /// `WireKVSError::NotFound`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// for revision in db.history("config").await.unwrap() {
+    ///     println!("v{} at {:?}: {}", revision.version, revision.updated_at, revision.value);
+    /// }
+    /// # }
+    /// ```
+    pub async fn history(&self, key: &str) -> Result<Vec<VersionedValue>, WireKVSError> {
+        let request = self.client.get(self.key_resource_url(key, &["history"])?);
+        let body = metrics::observe("history", async {
+            let response = self.send(true, request).await?;
+            parse_json(supported(response, "history")?, self.config.max_response_size).await
+        })
+        .await?;
+        let mut revisions: Vec<VersionedValue> = from_json(body)?;
+        revisions.sort_by_key(|revision| revision.version);
+        Ok(revisions)
+    }
+
+    /// Deletes `key` only if it is still at revision `version`
+    ///
+    /// Sends `If-Match: "<version>"` and returns `Ok(false)` without deleting
+    /// when the server answers HTTP 412 because the key changed since. The
+    /// request is never retried, since a retry after a lost response would
+    /// report the successful delete as a mismatch. A server that ignores
+    /// `If-Match` deletes unconditionally, so only use this against
+    /// deployments known to support it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let latest = db.history("lock").await.unwrap().pop().unwrap();
+    /// if !db.delete_if_match("lock", latest.version).await.unwrap() {
+    ///     println!("Lock was taken over in the meantime");
+    /// }
+    /// # }
+    /// ```
+    pub async fn delete_if_match(&self, key: &str, version: u64) -> Result<bool, WireKVSError> {
+        let request = self
+            .client
+            .delete(self.key_url(key)?)
+            .header(reqwest::header::IF_MATCH, format!("\"{}\"", version));
+        let deleted = metrics::observe("delete_if_match", async {
+            let response = supported(self.send(false, request).await?, "delete_if_match")?;
+            if response.status() == StatusCode::PRECONDITION_FAILED {
+                return Ok(false);
+            }
+            check_status(response).await?;
+            Ok(true)
+        })
+        .await?;
+        if deleted {
+            self.invalidate_cached(key);
+        }
+        Ok(deleted)
+    }
+}
+
+/// Turns the statuses of servers without history into `WireKVSError::Unsupported`
+fn supported(response: Response, operation: &'static str) -> Result<Response, WireKVSError> {
+    match response.status() {
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => Err(WireKVSError::Unsupported { operation }),
+        _ => Ok(response),
+    }
+}
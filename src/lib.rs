@@ -1,225 +1,2334 @@
-use reqwest;
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::{json, Value};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, WebSocketStream, MaybeTlsStream};
 use std::collections::HashMap;
-use tokio::sync::broadcast;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
-const API_BASE_URL: &str = "https://kvs.wireway.ch/v2";
+/// Logs at debug level when the `tracing` feature is enabled, and compiles to nothing otherwise
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+mod auth;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+#[cfg(feature = "cache")]
+mod cache;
+mod coalesce;
+mod config;
+mod database;
+mod error;
+mod event;
+#[cfg(feature = "history")]
+mod history;
+mod limit;
+#[cfg(feature = "in-memory")]
+mod memory;
+mod metrics;
+mod namespace;
+mod page;
+mod proxy;
+mod response;
+mod resync;
+mod retry;
+mod socket;
+mod store;
+mod streaming;
+mod subscription;
+mod timestamp;
+mod tls;
+#[cfg(feature = "ttl")]
+mod ttl;
 
+pub use builder::WireKVSBuilder;
+pub use config::{DatabaseConfig, DatabaseConfigUpdate, ReconnectPolicy, RetryPolicy, SocketAuth, WireKVSConfig};
+pub use database::{CreatedDatabase, DatabaseInfo};
+pub use error::WireKVSError;
+pub use event::{format_event, Event, EventEnvelope, ShutdownReport};
+#[cfg(feature = "history")]
+pub use history::VersionedValue;
+pub use limit::{ConcurrencyLimit, RateLimiter};
+#[cfg(feature = "in-memory")]
+pub use memory::InMemoryDatabase;
+pub use namespace::NamespacedDatabase;
+pub use page::Page;
+pub use response::{SetResponse, ValueWithMeta};
+pub use socket::{ConnectionDiagnostics, ConnectionState};
+pub use store::KeyValueStore;
+pub use subscription::Subscription;
+pub use tls::Certificate;
+use error::{batch_result, check_status, expect_type, from_json, json_type, parse_json, read_body};
+use event::Broadcaster;
+use socket::EventSocket;
+
+/// Handle to a single database and its event socket
+///
+/// Cloning is cheap: every clone shares the same HTTP connection pool and the
+/// same underlying event socket, so subscribers obtained from any clone see the
+/// same events. Closing the socket through one clone closes it for all of them.
+///
+/// Dropping the last clone closes the socket as well. Because `Drop` can't
+/// wait, the close handshake then runs on a background task and is cut short
+/// if the runtime shuts down first; call `close` to finish it before exiting.
+#[derive(Clone)]
 pub struct WireKVSDatabase {
     id: String,
-    access_key: String,
-    ws: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-    is_connected: bool,
-    tx: broadcast::Sender<Value>,
+    // Replaced when `WireKVSBuilder::on_access_key_rejected` hands out a new key
+    access_key: Arc<auth::Token>,
+    client: reqwest::Client,
+    config: WireKVSConfig,
+    socket_url: Url,
+    socket: Arc<Mutex<Option<EventSocket>>>,
+    state: watch::Receiver<ConnectionState>,
+    stats: Arc<socket::Stats>,
+    events: Broadcaster,
+    // Cancelled by `shutdown`, stopping every task spawned for this handle
+    cancel: CancellationToken,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<cache::Cache>>,
 }
 
 impl WireKVSDatabase {
     /// Creates a new WireKVSDatabase instance
     /// 
+    /// The event socket is connected before this returns. If the socket URL is
+    /// invalid or the connection is refused, the error is returned instead of
+    /// a half-initialized handle.
+    /// 
     /// # Example
+    /// ```no_run
+    /// # use wirekvs::WireKVSDatabase;
+    /// # async fn example() {
+    /// let db = WireKVSDatabase::new("database-id".to_string(), "access-key".to_string()).await.unwrap();
+    /// # }
     /// ```
-    /// let db = WireKVSDatabase::new("database-id".to_string(), "access-key".to_string()).await;
+    pub async fn new(id: String, access_key: String) -> Result<Self, WireKVSError> {
+        Self::with_config(id, access_key, WireKVSConfig::default()).await
+    }
+
+    /// Creates a new WireKVSDatabase instance using custom connection settings
+    /// 
+    /// Fails with `WireKVSError::InvalidConfig` if the settings don't pass the
+    /// same checks as `WireKVSBuilder::build`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::{WireKVSConfig, WireKVSDatabase};
+    /// # async fn example() {
+    /// let config = WireKVSConfig {
+    ///     base_url: "http://localhost:8080/v2".to_string(),
+    ///     ws_base_url: "ws://localhost:8080/events".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let db = WireKVSDatabase::with_config("database-id".to_string(), "access-key".to_string(), config).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn with_config(id: String, access_key: String, config: WireKVSConfig) -> Result<Self, WireKVSError> {
+        config.validate()?;
+        let client = config.http_client()?;
+        Self::with_http_client(id, access_key, client, config).await
+    }
+
+    /// Creates a new WireKVSDatabase instance that sends requests through `client`
+    /// 
+    /// Use this to reuse a `reqwest::Client` with its own proxy, TLS or pool
+    /// settings. Settings that are applied when the crate builds its own
+    /// client, such as `request_timeout`, `user_agent` and `default_headers`,
+    /// are ignored in favor of whatever `client` was built with. Crate-level
+    /// behavior like retries still follows `config`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::{WireKVSConfig, WireKVSDatabase};
+    /// # async fn example() {
+    /// let http = reqwest::Client::builder()
+    ///     .proxy(reqwest::Proxy::all("http://proxy.local:3128").unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// let db = WireKVSDatabase::with_http_client(
+    ///     "database-id".to_string(),
+    ///     "access-key".to_string(),
+    ///     http,
+    ///     WireKVSConfig::default(),
+    /// )
+    /// .await
+    /// .unwrap();
+    /// # }
     /// ```
-    pub async fn new(id: String, access_key: String) -> Self {
-        let (tx, _) = broadcast::channel(100);
+    pub async fn with_http_client(
+        id: String,
+        access_key: String,
+        client: reqwest::Client,
+        config: WireKVSConfig,
+    ) -> Result<Self, WireKVSError> {
+        Self::open(id, access_key, client, config, None).await
+    }
+
+    async fn open(
+        id: String,
+        access_key: String,
+        client: reqwest::Client,
+        config: WireKVSConfig,
+        refresh: Option<auth::Refresh>,
+    ) -> Result<Self, WireKVSError> {
+        config.validate()?;
+        let socket_url = socket_url(&config, &id, &access_key)?;
+        let events = Broadcaster::new(config.event_buffer_size);
+        let cancel = CancellationToken::new();
+        #[cfg(feature = "cache")]
+        let cache = config
+            .cache_capacity
+            .map(|capacity| cache::Cache::new(capacity, events.subscribe_internal(), cancel.clone()));
         let mut db = WireKVSDatabase {
             id,
-            access_key,
-            ws: None,
-            is_connected: false,
-            tx,
+            access_key: Arc::new(auth::Token::new(access_key, refresh)),
+            client,
+            config,
+            socket_url,
+            socket: Arc::new(Mutex::new(None)),
+            state: watch::channel(ConnectionState::Connecting).1,
+            stats: Arc::default(),
+            events,
+            cancel,
+            #[cfg(feature = "cache")]
+            cache,
+        };
+        db.setup_websocket().await?;
+        if let Some(max_jitter) = db.config.resync_on_reconnect {
+            let detached = WireKVSDatabase {
+                socket: Arc::new(Mutex::new(None)),
+                ..db.clone()
+            };
+            resync::spawn(detached, db.state.clone(), max_jitter, db.cancel.clone());
+        }
+        Ok(db)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), fields(db = %self.id), err(level = "debug")))]
+    async fn setup_websocket(&mut self) -> Result<(), WireKVSError> {
+        debug!(url = %self.event_socket_url(), "connecting event socket");
+        let socket = EventSocket::connect(
+            self.socket_url.clone(),
+            self.access_key.clone(),
+            &self.config,
+            self.events.clone(),
+            &self.cancel,
+        )
+        .await?;
+        self.state = socket.state();
+        self.stats = socket.stats();
+        *self.socket.lock().unwrap() = Some(socket);
+        Ok(())
+    }
+
+    /// Sends `request` with the current access key under `config.retry`
+    ///
+    /// A 401 is answered by asking `WireKVSBuilder::on_access_key_rejected`
+    /// for a new key and sending the request once more, unless its body is a
+    /// stream that can't be sent twice.
+    async fn send(&self, idempotent: bool, request: reqwest::RequestBuilder) -> Result<reqwest::Response, WireKVSError> {
+        if request.try_clone().is_none() {
+            let request = request.header(AUTHORIZATION, self.access_key.current());
+            return retry::send(&self.config, idempotent, request).await;
+        }
+        let with_key = |key: &str| {
+            request
+                .try_clone()
+                .expect("request was cloneable before")
+                .header(AUTHORIZATION, key)
         };
-        db.setup_websocket().await;
-        db
+        self.access_key.send(&self.config, idempotent, with_key).await
+    }
+
+    /// Builds an HTTP API URL from `segments`, see the module-level `build_url`
+    fn build_url(&self, segments: &[&str]) -> Result<Url, WireKVSError> {
+        build_url(&self.config.base_url, segments)
+    }
+
+    /// Builds the URL for a key, percent-encoding it so reserved characters
+    /// like `/`, `?` and `#` stay part of the key
+    fn key_url(&self, key: &str) -> Result<Url, WireKVSError> {
+        self.key_resource_url(key, &[])
+    }
+
+    /// Builds the URL of a resource below a key, such as its `ttl`
+    fn key_resource_url(&self, key: &str, resource: &[&str]) -> Result<Url, WireKVSError> {
+        check_key(key)?;
+        let mut segments = vec!["database", &self.id, key];
+        segments.extend_from_slice(resource);
+        self.build_url(&segments)
     }
 
-    async fn setup_websocket(&mut self) {
-        let ws_url = format!(
-            "wss://kvs.wireway.ch/events/{}?accessKey={}",
-            self.id,
-            urlencoding::encode(&self.access_key)
-        );
-        
-        let url = Url::parse(&ws_url).unwrap();
-        let (ws_stream, _) = connect_async(url.as_str()).await.expect("Failed to connect");
-        self.ws = Some(ws_stream);
-        self.is_connected = true;
+    /// Drops `key` from the local cache after this handle changed it
+    fn invalidate_cached(&self, _key: &str) {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            cache.invalidate(_key);
+        }
+    }
+
+    /// Cache generation to hand to `cache_written` once a write completes
+    fn cache_generation(&self) -> u64 {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            return cache.generation();
+        }
+        0
+    }
+
+    /// Updates the local cache after this handle set `key` to `value`,
+    /// caching the value itself with `WireKVSConfig::read_your_writes`
+    fn cache_written(&self, key: &str, _value: Value, _generation: u64) {
+        #[cfg(feature = "cache")]
+        if self.config.read_your_writes {
+            if let Some(cache) = &self.cache {
+                cache.store_written(key, _value, _generation);
+                return;
+            }
+        }
+        self.invalidate_cached(key);
     }
 
     /// Gets all entries from the database
     /// 
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
     /// let entries = db.get_all_entries().await.unwrap();
     /// println!("Entries: {:?}", entries);
+    /// # }
     /// ```
-    pub async fn get_all_entries(&self) -> Result<Value, reqwest::Error> {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&format!("{}/database/{}", API_BASE_URL, self.id))
-            .header("Authorization", &self.access_key)
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(response)
+    pub async fn get_all_entries(&self) -> Result<Value, WireKVSError> {
+        let request = self.client.get(self.build_url(&["database", &self.id])?);
+        metrics::observe("get_all_entries", async {
+            let response = self.send(true, request).await?;
+            parse_json(response, self.config.max_response_size).await
+        })
+        .await
     }
 
-    /// Gets a specific value by key
+    /// Lists every key in the database
+    /// 
+    /// The API has no keys-only endpoint, so this still downloads all entries
+    /// and discards the values locally. Keys are returned in lexicographic
+    /// order.
     /// 
     /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// for key in db.keys().await.unwrap() {
+    ///     println!("{}", key);
+    /// }
+    /// # }
     /// ```
+    pub async fn keys(&self) -> Result<Vec<String>, WireKVSError> {
+        let entries = page::entries_from_value(self.get_all_entries().await?)?;
+        let mut keys: Vec<String> = entries.into_iter().map(|(key, _)| key).collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Lists the keys that start with `prefix`, in lexicographic order
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let sessions = db.keys_with_prefix("session:").await.unwrap();
+    /// # }
+    /// ```
+    pub async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, WireKVSError> {
+        let mut keys = self.keys().await?;
+        keys.retain(|key| key.starts_with(prefix));
+        Ok(keys)
+    }
+
+    /// View of the keys starting with `prefix`, addressed without it
+    /// 
+    /// See `NamespacedDatabase` for how keys are mapped. The view shares this
+    /// handle's connection and event socket.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let sessions = db.namespace("session:");
+    /// sessions.set("abc", json!({ "user": 123 })).await.unwrap();
+    /// assert_eq!(db.get("session:abc").await.unwrap(), json!({ "user": 123 }));
+    /// # }
+    /// ```
+    pub fn namespace(&self, prefix: &str) -> NamespacedDatabase {
+        NamespacedDatabase::new(self.clone(), prefix.to_string())
+    }
+
+    /// Gets one page of entries
+    /// 
+    /// Pass `None` as the cursor to start from the beginning, then keep passing
+    /// `Page::next_cursor` until it is `None`. At most `limit` entries are
+    /// returned per page.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut cursor = None;
+    /// loop {
+    ///     let page = db.get_entries_page(cursor, 100).await.unwrap();
+    ///     for (key, value) in page.entries {
+    ///         println!("{} = {}", key, value);
+    ///     }
+    ///     match page.next_cursor {
+    ///         Some(next) => cursor = Some(next),
+    ///         None => break,
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_entries_page(&self, cursor: Option<String>, limit: usize) -> Result<Page, WireKVSError> {
+        let mut request = self
+            .client
+            .get(self.build_url(&["database", &self.id])?)
+            .query(&[("limit", limit.to_string())]);
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        metrics::observe("get_entries_page", async {
+            let response = self.send(true, request).await?;
+            Page::from_value(parse_json(response, self.config.max_response_size).await?)
+        })
+        .await
+    }
+
+    /// Walks all pages of entries lazily
+    /// 
+    /// Each page is only fetched once the previous one has been consumed. The
+    /// stream ends after the last page or after the first error.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut pages = Box::pin(db.pages(100));
+    /// while let Some(page) = pages.next().await {
+    ///     println!("Got {} entries", page.unwrap().entries.len());
+    /// }
+    /// # }
+    /// ```
+    pub fn pages(&self, limit: usize) -> impl Stream<Item = Result<Page, WireKVSError>> + '_ {
+        stream::unfold(Some(None), move |cursor| async move {
+            let cursor = cursor?;
+            match self.get_entries_page(cursor, limit).await {
+                Ok(page) => {
+                    let next = page.next_cursor.clone().map(Some);
+                    Some((Ok(page), next))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Walks every entry lazily, one `(key, value)` pair at a time
+    /// 
+    /// Fetches the database a page of 100 entries at a time, like `pages`, so
+    /// only one page is held in memory. A page that fails with an error that
+    /// may go away, such as a network error, a timeout, rate limiting or a
+    /// 5xx response, is yielded as an `Err` item and requested again on the
+    /// next poll, giving up after three failures in a row. Any other error is
+    /// yielded and ends the stream. Stop polling to give up earlier.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut entries = Box::pin(db.entries_stream());
+    /// while let Some(entry) = entries.next().await {
+    ///     let (key, value) = entry.unwrap();
+    ///     println!("{} = {}", key, value);
+    /// }
+    /// # }
+    /// ```
+    pub fn entries_stream(&self) -> impl Stream<Item = Result<(String, Value), WireKVSError>> + '_ {
+        let walk = page::Walk::new();
+        stream::unfold(walk, move |mut walk| async move {
+            loop {
+                if let Some(entry) = walk.buffered.pop_front() {
+                    return Some((Ok(entry), walk));
+                }
+                let cursor = walk.cursor.clone()?;
+                match self.get_entries_page(cursor, page::WALK_PAGE_SIZE).await {
+                    Ok(page) => walk.advance(page),
+                    Err(e) => {
+                        walk.fail(&e);
+                        return Some((Err(e), walk));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Gets a specific value by key
+    /// 
+    /// A missing key fails with `WireKVSError::NotFound`; use `get_opt` to
+    /// get `None` instead. With the `cache` feature and `WireKVSConfig::cache_capacity` set, the
+    /// value may be served from the local cache.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
     /// let value = db.get("my-key").await.unwrap();
     /// println!("Value: {:?}", value);
+    /// # }
     /// ```
-    pub async fn get(&self, key: &str) -> Result<Value, reqwest::Error> {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&format!("{}/database/{}/{}", API_BASE_URL, self.id, key))
-            .header("Authorization", &self.access_key)
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(response)
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), fields(db = %self.id), err(level = "debug")))]
+    pub async fn get(&self, key: &str) -> Result<Value, WireKVSError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            return cache.get_or_fetch(key, self.fetch(key)).await;
+        }
+        self.fetch(key).await
     }
 
-    /// Sets a value for a specific key
+    /// Gets a value by key, or `None` if the key doesn't exist
+    /// 
+    /// Only a 404 counts as missing. A key that holds JSON `null` is returned
+    /// as `Some(Value::Null)`, so absent and null values can be told apart.
+    /// Like `get`, this may be served from the local cache.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// match db.get_opt("greeting").await.unwrap() {
+    ///     Some(value) => println!("Greeting: {}", value),
+    ///     None => println!("No greeting yet"),
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_opt(&self, key: &str) -> Result<Option<Value>, WireKVSError> {
+        match self.get(key).await {
+            Ok(value) => Ok(Some(value)),
+            Err(WireKVSError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends the request behind `get` and returns the response unread
+    /// 
+    /// **Advanced, not covered by semver.** This is an escape hatch for
+    /// response details the typed API doesn't expose, such as specific
+    /// headers or streaming the body. The response comes back as the server
+    /// sent it: retries and rate limiting still apply, but the status is not
+    /// checked, the cache is bypassed and `WireKVSConfig::max_response_size`
+    /// isn't enforced. Its shape may change with the HTTP client the crate
+    /// builds on.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let response = db.get_raw("my-key").await.unwrap();
+    /// println!("Served by {:?}", response.headers().get("server"));
+    /// let body = response.bytes().await.unwrap();
+    /// # }
+    /// ```
+    pub async fn get_raw(&self, key: &str) -> Result<reqwest::Response, WireKVSError> {
+        let request = self.client.get(self.key_url(key)?);
+        metrics::observe("get_raw", self.send(true, request)).await
+    }
+
+    /// Sends the request behind `get_all_entries` and returns the response unread
+    /// 
+    /// **Advanced, not covered by semver**, with the same caveats as `get_raw`.
+    pub async fn get_all_entries_raw(&self) -> Result<reqwest::Response, WireKVSError> {
+        let request = self.client.get(self.build_url(&["database", &self.id])?);
+        metrics::observe("get_all_entries_raw", self.send(true, request)).await
+    }
+
+    /// Reads a value from the server, bypassing the local cache
+    async fn fetch(&self, key: &str) -> Result<Value, WireKVSError> {
+        let request = self.client.get(self.key_url(key)?);
+        metrics::observe("get", async {
+            let response = self.send(true, request).await?;
+            Ok(self.unwrap_envelope(parse_json(response, self.config.max_response_size).await?))
+        })
+        .await
+    }
+
+    /// Strips the metadata envelope with `WireKVSConfig::value_envelopes`
+    fn unwrap_envelope(&self, body: Value) -> Value {
+        if self.config.value_envelopes {
+            ValueWithMeta::from_body(body).value
+        } else {
+            body
+        }
+    }
+
+    /// Gets a value together with the metadata the server wrapped it in
+    /// 
+    /// See `ValueWithMeta` for the envelope shape this recognizes. A bare
+    /// value, which is what the hosted API returns, comes back with no
+    /// metadata. This always reads from the server, bypassing the local
+    /// cache.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let config = db.get_with_metadata("config").await.unwrap();
+    /// println!("{} at version {:?}", config.value, config.version);
+    /// # }
+    /// ```
+    pub async fn get_with_metadata(&self, key: &str) -> Result<ValueWithMeta, WireKVSError> {
+        let request = self.client.get(self.key_url(key)?);
+        metrics::observe("get", async {
+            let response = self.send(true, request).await?;
+            Ok(ValueWithMeta::from_body(parse_json(response, self.config.max_response_size).await?))
+        })
+        .await
+    }
+
+    /// Reads a value from the server, mapping a missing key to `None`
+    async fn fetch_optional(&self, key: &str) -> Result<Option<Value>, WireKVSError> {
+        match self.fetch(key).await {
+            Ok(value) => Ok(Some(value)),
+            Err(WireKVSError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gets a value along with the `ETag` the server sent for it
+    /// 
+    /// Pass the tag to `get_if_modified` to poll the key cheaply. The tag is
+    /// `None` if the server doesn't send an `ETag` header. This always reads
+    /// from the server, bypassing the local cache.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let (value, etag) = db.get_with_etag("config").await.unwrap();
+    /// # }
+    /// ```
+    pub async fn get_with_etag(&self, key: &str) -> Result<(Value, Option<String>), WireKVSError> {
+        let request = self.client.get(self.key_url(key)?);
+        metrics::observe("get", async {
+            let response = check_status(self.send(true, request).await?).await?;
+            let etag = etag(&response);
+            Ok((self.unwrap_envelope(parse_json(response, self.config.max_response_size).await?), etag))
+        })
+        .await
+    }
+
+    /// Gets a value only if it changed since `etag` was issued
+    /// 
+    /// Sends `If-None-Match` and returns `Ok(None)` when the server answers
+    /// `304 Not Modified`, so an unchanged value isn't downloaded again. Servers
+    /// that don't support ETags ignore the header and always send the value,
+    /// in which case this behaves like `get`. The response's new tag isn't
+    /// returned; use `get_with_etag` to refresh it after a change.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let (_, etag) = db.get_with_etag("config").await.unwrap();
+    /// if let Some(etag) = etag {
+    ///     if let Some(changed) = db.get_if_modified("config", &etag).await.unwrap() {
+    ///         println!("Config changed: {}", changed);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_if_modified(&self, key: &str, etag: &str) -> Result<Option<Value>, WireKVSError> {
+        let request = self
+            .client
+            .get(self.key_url(key)?)
+            .header(reqwest::header::IF_NONE_MATCH, etag);
+        metrics::observe("get", async {
+            let response = self.send(true, request).await?;
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            let value = parse_json(response, self.config.max_response_size).await?;
+            Ok(Some(self.unwrap_envelope(value)))
+        })
+        .await
+    }
+
+    /// Checks whether a key exists without fetching its value
+    /// 
+    /// Sends a `HEAD` request for the key, so no value is transferred. A 404 is
+    /// reported as `Ok(false)`; any other failure is returned as an error.
     /// 
     /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// if !db.exists("greeting").await.unwrap() {
+    ///     println!("No greeting yet");
+    /// }
+    /// # }
     /// ```
+    pub async fn exists(&self, key: &str) -> Result<bool, WireKVSError> {
+        let request = self.client.head(self.key_url(key)?);
+        metrics::observe("exists", async {
+            let response = self.send(true, request).await?;
+            match check_status(response).await {
+                Ok(_) => Ok(true),
+                Err(WireKVSError::NotFound) => Ok(false),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    }
+
+    /// Gets a value by key and deserializes it into `T`
+    /// 
+    /// The value is deserialized exactly as `get` returns it, so `T` should
+    /// describe the stored value itself rather than any response wrapper.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// #[derive(serde::Deserialize)]
+    /// struct Profile {
+    ///     name: String,
+    /// }
+    /// 
+    /// let profile: Profile = db.get_as("profile").await.unwrap();
+    /// println!("Name: {}", profile.name);
+    /// # }
+    /// ```
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, WireKVSError> {
+        from_json(self.get(key).await?)
+    }
+
+    /// Gets a string value, or `None` if the key doesn't exist
+    /// 
+    /// Values are not coerced: anything but a JSON string, including
+    /// numbers and `null`, fails with `WireKVSError::TypeMismatch`. The same
+    /// strict rules apply to `get_i64`, `get_f64` and `get_bool`, so `"42"`
+    /// is not an integer and `"true"` is not a bool.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let name = db.get_string("name").await.unwrap().unwrap_or_default();
+    /// # }
+    /// ```
+    pub async fn get_string(&self, key: &str) -> Result<Option<String>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "string", |value| match value {
+            Value::String(text) => Some(text),
+            _ => None,
+        })
+    }
+
+    /// Gets an integer value, or `None` if the key doesn't exist
+    /// 
+    /// Only JSON integers within the range of `i64` are accepted. Floats fail
+    /// with `WireKVSError::TypeMismatch` even if they have no fractional
+    /// part, and so do strings of digits.
+    pub async fn get_i64(&self, key: &str) -> Result<Option<i64>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "integer", |value| value.as_i64())
+    }
+
+    /// Gets a number as a float, or `None` if the key doesn't exist
+    /// 
+    /// Any JSON number is accepted, so integers are converted and may lose
+    /// precision beyond 2^53. Other types fail with
+    /// `WireKVSError::TypeMismatch`.
+    pub async fn get_f64(&self, key: &str) -> Result<Option<f64>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "number", |value| value.as_f64())
+    }
+
+    /// Gets a bool value, or `None` if the key doesn't exist
+    /// 
+    /// Only JSON `true` and `false` are accepted; other types fail with
+    /// `WireKVSError::TypeMismatch`.
+    pub async fn get_bool(&self, key: &str) -> Result<Option<bool>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "bool", |value| value.as_bool())
+    }
+
+    /// Sets a value for a specific key
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
     /// db.set("greeting", json!("Hello!")).await.unwrap();
+    /// # }
     /// ```
-    pub async fn set(&self, key: &str, value: Value) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-        client
-            .post(&format!("{}/database/{}/{}", API_BASE_URL, self.id, key))
-            .header("Authorization", &self.access_key)
-            .json(&value)
-            .send()
-            .await?;
+    pub async fn set(&self, key: &str, value: Value) -> Result<(), WireKVSError> {
+        self.set_returning(key, value).await?;
         Ok(())
     }
 
-    /// Deletes a value by key
+    /// Sets a value and returns the write metadata sent back by the server
+    /// 
+    /// See `SetResponse` for which fields are recognized. Use this for
+    /// auditing or to track revisions; `set` discards the metadata.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let written = db.set_returning("greeting", json!("Hello!")).await.unwrap();
+    /// if let Some(version) = written.version {
+    ///     println!("Now at version {}", version);
+    /// }
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, value), fields(db = %self.id), err(level = "debug")))]
+    pub async fn set_returning(&self, key: &str, value: Value) -> Result<SetResponse, WireKVSError> {
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .json(&value);
+        let generation = self.cache_generation();
+        let written = metrics::observe("set", async {
+            let response = self.send(false, request).await?;
+            let body = read_body(check_status(response).await?, self.config.max_response_size).await?;
+            Ok(SetResponse::from_body(&body))
+        })
+        .await?;
+        self.cache_written(key, value, generation);
+        Ok(written)
+    }
+
+    /// Sets a value, sending `idempotency_key` so the server can drop duplicate writes
+    /// 
+    /// The key is sent as an `Idempotency-Key` header, unchanged on every
+    /// attempt, so this write is retried under `WireKVSConfig::retry` like a
+    /// read, even without `RetryPolicy::retry_non_idempotent`. Generate a
+    /// fresh, unique key (such as a UUID) per logical write and reuse it only
+    /// when repeating that same write, e.g. after a crash.
+    /// 
+    /// Deduplication is up to the server. Deployments that ignore the header
+    /// apply every attempt that reaches them, so a retried write may still be
+    /// applied twice. A key that isn't a valid header value fails with
+    /// `WireKVSError::Network`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.set_idempotent("balance", json!(120), "deposit-7f3c9a").await.unwrap();
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, value), fields(db = %self.id), err(level = "debug")))]
+    pub async fn set_idempotent(&self, key: &str, value: Value, idempotency_key: &str) -> Result<(), WireKVSError> {
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .header("Idempotency-Key", idempotency_key)
+            .json(&value);
+        let generation = self.cache_generation();
+        metrics::observe("set", async {
+            let response = self.send(true, request).await?;
+            check_status(response).await?;
+            Ok(())
+        })
+        .await?;
+        self.cache_written(key, value, generation);
+        Ok(())
+    }
+
+    /// Serializes `value` and sets it for a specific key
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// #[derive(serde::Serialize)]
+    /// struct Profile {
+    ///     name: String,
+    /// }
+    /// 
+    /// let profile = Profile { name: "Ferris".to_string() };
+    /// db.set_typed("profile", &profile).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn set_typed<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), WireKVSError> {
+        self.set(key, serde_json::to_value(value)?).await
+    }
+
+    /// Sets a value given as already serialized JSON, sending it unchanged
+    /// 
+    /// Skips parsing into a `Value` and serializing again, for callers that
+    /// forward payloads they received elsewhere. The text is only checked to
+    /// be well-formed JSON, failing with `WireKVSError::Decode` otherwise.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.set_raw("profile", r#"{"name":"Ferris"}"#).await.unwrap();
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, json), fields(db = %self.id), err(level = "debug")))]
+    pub async fn set_raw(&self, key: &str, json: &str) -> Result<(), WireKVSError> {
+        serde_json::from_str::<serde::de::IgnoredAny>(json)?;
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(json.to_string());
+        metrics::observe("set", async {
+            let response = self.send(false, request).await?;
+            check_status(response).await?;
+            Ok(())
+        })
+        .await?;
+        self.invalidate_cached(key);
+        Ok(())
+    }
+
+    /// Sets `new` only if the current value equals `expected`
+    /// 
+    /// `None` as `expected` means the key must not exist yet. Returns
+    /// `Ok(false)` without writing when the precondition doesn't hold.
+    /// 
+    /// The WireKVS API has no conditional write, so this reads the current
+    /// value and then writes, sending the `ETag` of the read as `If-Match`
+    /// (or `If-None-Match: *` for a missing key). A server that honors those
+    /// headers rejects the write with `412 Precondition Failed` if the key
+    /// changed in between, reported as `Ok(false)`. The hosted API ignores
+    /// them, so there another client writing the key between the two requests
+    /// is not detected, and the swap is only safe against writers that
+    /// coordinate through other means.
     /// 
     /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let swapped = db.compare_and_set("state", Some(json!("idle")), json!("running")).await.unwrap();
+    /// if !swapped {
+    ///     println!("State changed in the meantime");
+    /// }
+    /// # }
     /// ```
+    pub async fn compare_and_set(&self, key: &str, expected: Option<Value>, new: Value) -> Result<bool, WireKVSError> {
+        let (current, etag) = self.fetch_versioned(key).await?;
+        if current != expected {
+            return Ok(false);
+        }
+        Ok(self.set_if_unchanged(key, current.is_none(), etag, new).await?.is_ok())
+    }
+
+    /// Reads a value and its `ETag` from the server, mapping a missing key to `None`
+    async fn fetch_versioned(&self, key: &str) -> Result<(Option<Value>, Option<String>), WireKVSError> {
+        match self.get_with_etag(key).await {
+            Ok((value, etag)) => Ok((Some(value), etag)),
+            Err(WireKVSError::NotFound) => Ok((None, None)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes `new` guarded by the `ETag` of the last read, or by
+    /// `If-None-Match: *` if the key was `missing`
+    ///
+    /// Only a `412 Precondition Failed` triggers another read, whose value
+    /// and tag are handed back so a retry can start from them.
+    async fn set_if_unchanged(
+        &self,
+        key: &str,
+        missing: bool,
+        etag: Option<String>,
+        new: Value,
+    ) -> Result<Result<(), (Option<Value>, Option<String>)>, WireKVSError> {
+        let mut request = self.client.post(self.key_url(key)?).json(&new);
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        } else if missing {
+            request = request.header(reqwest::header::IF_NONE_MATCH, "*");
+        }
+        let generation = self.cache_generation();
+        let written = metrics::observe("set", async {
+            let response = self.send(false, request).await?;
+            if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+                return Ok(false);
+            }
+            check_status(response).await?;
+            Ok(true)
+        })
+        .await?;
+        if !written {
+            self.invalidate_cached(key);
+            return Ok(Err(self.fetch_versioned(key).await?));
+        }
+        self.cache_written(key, new, generation);
+        Ok(Ok(()))
+    }
+
+    /// Read-modify-write loop behind `increment`, `update` and friends
+    ///
+    /// `f` maps the current value to the value to store and the result to
+    /// return, or to `None` and a result to return without writing. The
+    /// write is conditional on the value `f` saw, see `compare_and_set`, so
+    /// each attempt costs one read and one write. When the server reports
+    /// the value changed, `f` runs again on the value read after the
+    /// conflict. Gives up with `WireKVSError::Conflict` after
+    /// `WireKVSConfig::max_cas_attempts`.
+    async fn modify<T>(
+        &self,
+        key: &str,
+        mut f: impl FnMut(Option<&Value>) -> Result<(Option<Value>, T), WireKVSError>,
+    ) -> Result<T, WireKVSError> {
+        let attempts = self.config.max_cas_attempts.max(1);
+        let (mut current, mut etag) = self.fetch_versioned(key).await?;
+        for _ in 0..attempts {
+            let (new, result) = f(current.as_ref())?;
+            let Some(new) = new else {
+                return Ok(result);
+            };
+            match self.set_if_unchanged(key, current.is_none(), etag, new).await? {
+                Ok(()) => return Ok(result),
+                Err(found) => (current, etag) = found,
+            }
+        }
+        Err(WireKVSError::Conflict { attempts })
+    }
+
+    /// Sets a value only if the key doesn't exist yet
+    /// 
+    /// Returns `Ok(true)` if the value was written and `Ok(false)` without
+    /// writing if the key already holds a value. Use it for one-time
+    /// initialization or as the basis of a lock.
+    /// 
+    /// The current value is read first, then the write is sent with
+    /// `If-None-Match: *`. A server that honors the header rejects the write
+    /// with `412 Precondition Failed` if the key was created in between, which
+    /// is reported as `Ok(false)`, making the check atomic. The hosted API
+    /// ignores the header, so there another client creating the key between
+    /// the read and the write goes unnoticed and its value is overwritten.
+    /// The write is not retried, since a retry could report its own earlier
+    /// attempt as an existing value.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// if db.set_if_absent("initialized", json!(true)).await.unwrap() {
+    ///     println!("First run, setting up");
+    /// }
+    /// # }
+    /// ```
+    pub async fn set_if_absent(&self, key: &str, value: Value) -> Result<bool, WireKVSError> {
+        if self.fetch_optional(key).await?.is_some() {
+            return Ok(false);
+        }
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .header(reqwest::header::IF_NONE_MATCH, "*")
+            .json(&value);
+        let generation = self.cache_generation();
+        let written = metrics::observe("set", async {
+            let response = self.send(false, request).await?;
+            if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+                return Ok(false);
+            }
+            check_status(response).await?;
+            Ok(true)
+        })
+        .await?;
+        if written {
+            self.cache_written(key, value, generation);
+        } else {
+            self.invalidate_cached(key);
+        }
+        Ok(written)
+    }
+
+    /// Adds `delta` to an integer value and returns the new total
+    /// 
+    /// A missing key counts as zero. If the stored value is not an integer,
+    /// `WireKVSError::TypeMismatch` is returned and nothing is written; numeric
+    /// strings like `"42"` are not coerced. The sum saturates at the bounds of
+    /// `i64`.
+    /// 
+    /// There is no server-side increment, so this runs `compare_and_set` in a
+    /// loop. Under contention each lost race costs another read and write,
+    /// and after `WireKVSConfig::max_cas_attempts` attempts it gives up with
+    /// `WireKVSError::Conflict`. Since `compare_and_set` itself is a read
+    /// followed by a write, concurrent increments from other clients can still
+    /// be lost on servers that ignore its precondition headers.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let visits = db.increment("visits", 1).await.unwrap();
+    /// println!("Visit number {}", visits);
+    /// # }
+    /// ```
+    pub async fn increment(&self, key: &str, delta: i64) -> Result<i64, WireKVSError> {
+        self.modify(key, |current| {
+            let total = match current {
+                None => delta,
+                Some(value) => match value.as_i64() {
+                    Some(n) => n.saturating_add(delta),
+                    None => {
+                        return Err(WireKVSError::TypeMismatch {
+                            expected: "integer",
+                            found: json_type(value),
+                        })
+                    }
+                },
+            };
+            Ok((Some(json!(total)), total))
+        })
+        .await
+    }
+
+    /// Replaces a value with the result of `f` applied to the current value
+    /// 
+    /// `f` receives `None` if the key doesn't exist yet. The new value is
+    /// written with `compare_and_set`, so if the value changes between the
+    /// read and the write, `f` runs again on the value read after the
+    /// conflict. Retries happen immediately, without backoff, and after
+    /// `WireKVSConfig::max_cas_attempts` attempts (10 by default) this gives
+    /// up with `WireKVSError::Conflict`. Returns the value that was stored.
+    /// 
+    /// As with `increment`, the check and the write are separate requests, so
+    /// this narrows the window for lost updates but can't rule them out.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::{json, Value};
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let tags = db
+    ///     .update("tags", |current| {
+    ///         let mut tags = current.unwrap_or_else(|| json!([]));
+    ///         if let Value::Array(items) = &mut tags {
+    ///             items.push(json!("new"));
+    ///         }
+    ///         tags
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn update<F>(&self, key: &str, mut f: F) -> Result<Value, WireKVSError>
+    where
+        F: FnMut(Option<Value>) -> Value,
+    {
+        self.modify(key, |current| {
+            let new = f(current.cloned());
+            Ok((Some(new.clone()), new))
+        })
+        .await
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to a value and returns the result
+    /// 
+    /// Fields of a `patch` object replace the fields of the same name in the
+    /// stored object, nested objects are merged the same way, and `null`
+    /// removes a field. A missing key or non-object value is patched as if it
+    /// were `{}`, and a `patch` that isn't an object replaces the value
+    /// outright. Arrays are replaced, never merged.
+    /// 
+    /// The API has no PATCH endpoint, so the merge happens client-side through
+    /// `update`: it retries when the value changes concurrently, but like
+    /// `update` it can't rule out lost updates from other clients.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.set("profile", json!({ "name": "Ferris", "age": 8, "tags": ["crab"] })).await.unwrap();
+    /// let profile = db.patch("profile", json!({ "age": 9, "tags": null })).await.unwrap();
+    /// assert_eq!(profile, json!({ "name": "Ferris", "age": 9 }));
+    /// # }
+    /// ```
+    pub async fn patch(&self, key: &str, patch: Value) -> Result<Value, WireKVSError> {
+        self.update(key, |current| merge_patch(current.unwrap_or(Value::Null), &patch))
+            .await
+    }
+
+    /// Appends `element` to an array value and returns the resulting array
+    /// 
+    /// A missing key is created as a one-element array. If the stored value
+    /// is not an array, `WireKVSError::TypeMismatch` is returned and nothing
+    /// is written.
+    /// 
+    /// Like `increment`, this runs `compare_and_set` in a loop, retrying when
+    /// the array changes concurrently and giving up with
+    /// `WireKVSError::Conflict` after `WireKVSConfig::max_cas_attempts`
+    /// attempts. As there, other clients' concurrent appends can still be
+    /// lost, and each attempt resends the whole array, so very long arrays are
+    /// better split across keys.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let log = db.array_append("log", json!({ "event": "login" })).await.unwrap();
+    /// println!("{} entries", log.as_array().unwrap().len());
+    /// # }
+    /// ```
+    pub async fn array_append(&self, key: &str, element: Value) -> Result<Value, WireKVSError> {
+        self.modify(key, |current| {
+            let mut items = array_items(current)?;
+            items.push(element.clone());
+            let new = Value::Array(items);
+            Ok((Some(new.clone()), new))
+        })
+        .await
+    }
+
+    /// Removes every element equal to `element` from an array value and
+    /// returns the resulting array
+    /// 
+    /// A missing key yields an empty array, and nothing is written if no
+    /// element matched. A value that is not an array fails with
+    /// `WireKVSError::TypeMismatch`. Concurrent changes are retried the same
+    /// way as in `array_append`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let tags = db.array_remove("tags", &json!("draft")).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn array_remove(&self, key: &str, element: &Value) -> Result<Value, WireKVSError> {
+        self.modify(key, |current| {
+            let items = array_items(current)?;
+            let len = items.len();
+            let new = Value::Array(items.into_iter().filter(|item| item != element).collect());
+            let changed = new.as_array().is_some_and(|items| items.len() != len);
+            Ok((changed.then(|| new.clone()), new))
+        })
+        .await
+    }
+
+    /// Gets many keys at once
+    /// 
+    /// There is no multi-get endpoint, so this sends one `get` per key
+    /// concurrently, with at most `WireKVSConfig::batch_concurrency` (8 by
+    /// default) in flight at a time. Keys that don't exist are left out of the
+    /// returned map, and the map has no particular order. If any other request
+    /// fails, all failures are returned together as `WireKVSError::Batch`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let values = db.get_many(&["greeting", "count"]).await.unwrap();
+    /// if let Some(greeting) = values.get("greeting") {
+    ///     println!("Greeting: {}", greeting);
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_many(&self, keys: &[&str]) -> Result<HashMap<String, Value>, WireKVSError> {
+        let results: Vec<_> = stream::iter(keys)
+            .map(|key| async move { (key.to_string(), self.get(key).await) })
+            .buffer_unordered(self.config.batch_concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut values = HashMap::new();
+        let mut failures = Vec::new();
+        for (key, result) in results {
+            match result {
+                Ok(value) => {
+                    values.insert(key, value);
+                }
+                Err(WireKVSError::NotFound) => {}
+                Err(e) => failures.push((key, e)),
+            }
+        }
+        batch_result(failures)?;
+        Ok(values)
+    }
+
+    /// Sets many keys at once
+    /// 
+    /// Requests are sent concurrently, with at most
+    /// `WireKVSConfig::batch_concurrency` (8 by default) in flight at a time.
+    /// Every entry is attempted even if some fail; failures are returned
+    /// together as `WireKVSError::Batch`, listing the key each error belongs to.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use std::collections::HashMap;
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut entries = HashMap::new();
+    /// entries.insert("greeting".to_string(), json!("Hello!"));
+    /// entries.insert("count".to_string(), json!(42));
+    /// db.set_many(entries).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn set_many(&self, entries: HashMap<String, Value>) -> Result<(), WireKVSError> {
+        let failures = stream::iter(entries)
+            .map(|(key, value)| async move {
+                let result = self.set(&key, value).await;
+                (key, result)
+            })
+            .buffer_unordered(self.config.batch_concurrency.max(1))
+            .filter_map(|(key, result)| async move { result.err().map(|e| (key, e)) })
+            .collect()
+            .await;
+        batch_result(failures)
+    }
+
+    /// Deletes a value by key
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
     /// db.delete("my-key").await.unwrap();
+    /// # }
     /// ```
-    pub async fn delete(&self, key: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-        client
-            .delete(&format!("{}/database/{}/{}", API_BASE_URL, self.id, key))
-            .header("Authorization", &self.access_key)
-            .send()
-            .await?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), fields(db = %self.id), err(level = "debug")))]
+    pub async fn delete(&self, key: &str) -> Result<(), WireKVSError> {
+        let request = self.client.delete(self.key_url(key)?);
+        metrics::observe("delete", async {
+            let response = self.send(true, request).await?;
+            check_status(response).await
+        })
+        .await?;
+        self.invalidate_cached(key);
         Ok(())
     }
 
-    /// Subscribe to real-time database events
+    /// Closes the event socket with a proper close handshake
+    /// 
+    /// Server-side event subscriptions are released right away instead of
+    /// waiting for a timeout. The socket is shared, so this closes it for every
+    /// clone of this handle. Calling `close` again is a no-op.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(mut db: wirekvs::WireKVSDatabase) {
+    /// db.close().await;
+    /// # }
+    /// ```
+    pub async fn close(&mut self) {
+        let socket = self.socket.lock().unwrap().take();
+        if let Some(mut socket) = socket {
+            socket.close().await;
+        }
+    }
+
+    /// Stops every background task of this handle and closes the event socket
+    /// 
+    /// Goes further than `close`: besides the event loop and its keepalive,
+    /// the tasks behind `subscribe_filtered`, `subscribe_coalesced`, the
+    /// cache and `WireKVSConfig::resync_on_reconnect` stop as well, so their
+    /// receivers end. The event socket is closed with a close handshake and
+    /// this returns once its task has finished. Like `close`, this applies to
+    /// every clone. HTTP methods keep working afterwards, but no more events
+    /// arrive.
+    /// 
+    /// HTTP requests don't run on background tasks: dropping the future of a
+    /// call such as `get_all_entries` cancels its request, e.g. inside
+    /// `tokio::time::timeout` or a `select!`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.shutdown().await;
+    /// assert!(!db.is_connected());
+    /// # }
+    /// ```
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        let socket = self.socket.lock().unwrap().take();
+        if let Some(mut socket) = socket {
+            socket.close().await;
+        }
+    }
+
+    /// Shuts down like `shutdown`, but first gives subscribers up to
+    /// `drain_timeout` to receive the events already buffered for them
+    /// 
+    /// Events arriving from the server are discarded from the moment this is
+    /// called, while the socket stays open until the buffers are drained or
+    /// the timeout elapses. The report counts how many of the buffered
+    /// events every subscriber received and how many were left behind.
+    /// Subscribers need to keep receiving for the drain to finish; events
+    /// parked in the first receiver that nobody claimed with `subscribe` are
+    /// discarded right away and not counted.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # async fn example(mut db: wirekvs::WireKVSDatabase) {
+    /// let report = db.graceful_shutdown(Duration::from_secs(5)).await;
+    /// if report.dropped > 0 {
+    ///     println!("{} events were never processed", report.dropped);
+    /// }
+    /// # }
+    /// ```
+    pub async fn graceful_shutdown(&mut self, drain_timeout: std::time::Duration) -> ShutdownReport {
+        self.events.seal();
+        let buffered = self.events.pending();
+        let drained = async {
+            while self.events.pending() > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        };
+        let _ = tokio::time::timeout(drain_timeout, drained).await;
+        let dropped = self.events.pending().min(buffered);
+        self.shutdown().await;
+        ShutdownReport {
+            delivered: buffered - dropped,
+            dropped,
+        }
+    }
+
+    /// Moves the value stored under `from` to `to`
+    /// 
+    /// Fails with `WireKVSError::NotFound` if `from` doesn't exist. If `to`
+    /// already holds a value, it is replaced when `overwrite` is set and the
+    /// call fails with `WireKVSError::AlreadyExists` otherwise, leaving both
+    /// keys untouched.
+    /// 
+    /// There is no move endpoint, so this reads `from`, writes `to` and then
+    /// deletes `from`. The steps are not atomic: readers can see the value
+    /// under both keys in between, a write to `from` during the move is lost,
+    /// and if the final delete fails the value stays under both keys.
+    /// Subscribers see a set event for `to` followed by a delete for `from`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.rename("draft", "published", false).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn rename(&self, from: &str, to: &str, overwrite: bool) -> Result<(), WireKVSError> {
+        check_key(to)?;
+        let value = self.fetch(from).await?;
+        if from == to {
+            return Ok(());
+        }
+        if overwrite {
+            self.set(to, value).await?;
+        } else if !self.set_if_absent(to, value).await? {
+            return Err(WireKVSError::AlreadyExists { key: to.to_string() });
+        }
+        self.delete(from).await
+    }
+
+    /// Deletes many keys at once
+    /// 
+    /// Requests are sent concurrently, with at most
+    /// `WireKVSConfig::batch_concurrency` in flight at a time. Every key is
+    /// attempted; keys that failed are returned together as
+    /// `WireKVSError::Batch`, so any key not listed there was deleted.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::WireKVSError;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// match db.delete_many(&["a", "b", "c"]).await {
+    ///     Ok(()) => println!("All keys deleted"),
+    ///     Err(WireKVSError::Batch { failures }) => {
+    ///         for (key, e) in failures {
+    ///             println!("{} not deleted: {}", key, e);
+    ///         }
+    ///     }
+    ///     Err(e) => println!("Error: {}", e),
+    /// }
+    /// # }
+    /// ```
+    pub async fn delete_many(&self, keys: &[&str]) -> Result<(), WireKVSError> {
+        let failures = stream::iter(keys)
+            .map(|key| async move { (key.to_string(), self.delete(key).await) })
+            .buffer_unordered(self.config.batch_concurrency.max(1))
+            .filter_map(|(key, result)| async move { result.err().map(|e| (key, e)) })
+            .collect()
+            .await;
+        batch_result(failures)
+    }
+
+    /// Deletes every key in the database
+    /// 
+    /// This cannot be undone. The database itself, its id and its access key
+    /// are kept. The server has no truncate endpoint, so this lists all keys
+    /// and removes them with `delete_many`: subscribers receive one
+    /// `Event::Deleted` per key, keys written concurrently may survive, and
+    /// keys that could not be deleted are reported in `WireKVSError::Batch`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.clear().await.unwrap();
+    /// assert!(db.keys().await.unwrap().is_empty());
+    /// # }
+    /// ```
+    pub async fn clear(&self) -> Result<(), WireKVSError> {
+        let keys = self.keys().await?;
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.delete_many(&keys).await
+    }
+
+    /// Id of the database this handle operates on
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::DatabaseConfig;
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let db = client.create_and_connect("My Database", DatabaseConfig::default()).await.unwrap();
+    /// println!("Created database {}", db.id());
+    /// # }
+    /// ```
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Access key this handle authenticates with
+    /// 
+    /// The key grants full access to the database, so treat it like a
+    /// password and keep it out of logs. After
+    /// `WireKVSBuilder::on_access_key_rejected` handed out a new key, this
+    /// returns the new one.
+    pub fn access_key(&self) -> String {
+        self.access_key.current()
+    }
+
+    /// URL of the event socket, with the access key replaced so it's safe to log
+    /// 
+    /// Includes the extra query parameters set with
+    /// `WireKVSConfig::ws_query_params`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// println!("Events come from {}", db.event_socket_url());
+    /// # }
+    /// ```
+    pub fn event_socket_url(&self) -> Url {
+        socket::redact(&self.socket_url)
+    }
+
+    /// Whether the event socket is currently live
     /// 
     /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// if !db.is_connected() {
+    ///     println!("Events are not being received right now");
+    /// }
+    /// # }
     /// ```
+    pub fn is_connected(&self) -> bool {
+        *self.state.borrow() == ConnectionState::Connected
+    }
+
+    /// Snapshot of the event socket's health, for debugging missing events
+    /// 
+    /// Reads counters the socket keeps anyway, so it's cheap to call and
+    /// doesn't touch the connection. `events_received` counts frames from
+    /// the server, not the `Connected`/`Disconnected` events the client adds.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let diagnostics = db.connection_diagnostics();
+    /// println!(
+    ///     "connected: {}, {} events, {} reconnects, last event at {:?}",
+    ///     diagnostics.connected,
+    ///     diagnostics.events_received,
+    ///     diagnostics.reconnect_count,
+    ///     diagnostics.last_event_at,
+    /// );
+    /// # }
+    /// ```
+    pub fn connection_diagnostics(&self) -> ConnectionDiagnostics {
+        self.stats.diagnose(*self.state.borrow(), self.events.receiver_count())
+    }
+
+    /// Sends a JSON text frame to the server over the event socket
+    /// 
+    /// The event protocol doesn't document client messages, so what the
+    /// server does with them is up to the deployment. Frames from concurrent
+    /// callers and clones are written one at a time. Fails with
+    /// `WireKVSError::NotConnected` while the socket is reconnecting or after
+    /// it was closed, and with `WireKVSError::WebSocket` if the write fails.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.send_ws(json!({ "type": "ping" })).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn send_ws(&self, message: Value) -> Result<(), WireKVSError> {
+        let outbox = self.socket.lock().unwrap().as_ref().map(EventSocket::outbox);
+        match outbox {
+            Some(outbox) => outbox.send(&message).await,
+            None => Err(WireKVSError::NotConnected),
+        }
+    }
+
+    /// Watch the event socket's connection state
+    /// 
+    /// This is separate from the event channels, so status changes are never
+    /// delayed by or mixed in with data events. A typical use is to re-read
+    /// state after the socket comes back as `Connected`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::ConnectionState;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut state = db.connection_state();
+    /// while state.changed().await.is_ok() {
+    ///     if *state.borrow() == ConnectionState::Connected {
+    ///         println!("Reconnected, resyncing");
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// Subscribe to real-time database events
+    /// 
+    /// If the event socket drops, it is reconnected in the background according
+    /// to `WireKVSConfig::reconnect`. Subscribers receive a synthetic
+    /// `{"type": "disconnected"}` event when the socket drops and a
+    /// `{"type": "connected"}` event once it is back, so they know to re-read
+    /// any state that may have changed in between.
+    /// 
+    /// The channel buffers a limited number of events. A receiver that falls
+    /// further behind gets `RecvError::Lagged` from `recv`, which ends a
+    /// `while let Ok(..)` loop like the one below. Use `subscription` for a
+    /// receiver that skips past the gap instead.
+    /// 
+    /// The first receiver obtained from a handle, or from any of its clones,
+    /// starts at the moment the socket connected, before `new` returned, so
+    /// events that arrive before you get around to subscribing are not lost.
+    /// Every later receiver starts at the moment it subscribes. In both cases
+    /// events are seen in the order the socket received them. The same holds
+    /// separately for the first receiver from `subscribe_typed` or the helpers
+    /// built on it. To start from the full current state, subscribe first and
+    /// then read `get_all_entries`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
     /// let mut rx = db.subscribe();
     /// tokio::spawn(async move {
     ///     while let Ok(event) = rx.recv().await {
     ///         println!("Event: {:?}", event);
     ///     }
     /// });
+    /// # }
     /// ```
     pub fn subscribe(&self) -> broadcast::Receiver<Value> {
-        self.tx.subscribe()
+        self.events.subscribe_raw()
+    }
+
+    /// Subscribe to real-time database events parsed into `Event`
+    /// 
+    /// Receives the same events as `subscribe`, including the synthetic
+    /// `Connected` and `Disconnected` events around reconnects. Frames that
+    /// don't match a known event type arrive as `Event::Unknown`. Each event
+    /// comes in an `EventEnvelope` with the frame it was parsed from, for
+    /// fields `Event` doesn't model yet; the frame is parsed once and shared
+    /// by every receiver through an `Arc`. Slow receivers get
+    /// `RecvError::Lagged` just like with `subscribe`, and the first receiver
+    /// also sees the events since the socket connected.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::Event;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut rx = db.subscribe_typed();
+    /// tokio::spawn(async move {
+    ///     while let Ok(envelope) = rx.recv().await {
+    ///         match envelope.event {
+    ///             Event::Set { key, value } => println!("{} = {}", key, value),
+    ///             Event::Deleted { key } => println!("{} deleted", key),
+    ///             other => println!("Event: {:?}", other),
+    ///         }
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn subscribe_typed(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.events.subscribe_typed()
+    }
+
+    /// Subscribe to typed events without stopping when the receiver lags
+    /// 
+    /// See `Subscription` for how skipped events are reported and how to
+    /// resync state after a lag.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut events = db.subscription();
+    /// while let Some(event) = events.recv().await {
+    ///     println!("Event: {:?}", event);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscription(&self) -> Subscription {
+        Subscription::new(self.subscribe_typed())
+    }
+
+    /// Stream of real-time database events parsed into `Event`
+    /// 
+    /// If the stream falls too far behind the socket, the events it missed
+    /// are skipped and the stream continues with the oldest event still
+    /// buffered. Use `subscribe_typed` if you need to observe lag explicitly.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # use wirekvs::Event;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut deletions = db
+    ///     .event_stream()
+    ///     .filter(|event| futures::future::ready(matches!(event, Event::Deleted { .. })));
+    /// while let Some(event) = deletions.next().await {
+    ///     println!("Deleted: {:?}", event.key());
+    /// }
+    /// # }
+    /// ```
+    pub fn event_stream(&self) -> impl Stream<Item = Event> + Unpin {
+        BroadcastStream::new(self.subscribe_typed())
+            .filter_map(|envelope| future::ready(envelope.ok().map(|envelope| envelope.event)))
+    }
+
+    /// Stream of events for a single key
+    /// 
+    /// Filtering happens client-side. Only events that carry exactly this key
+    /// are yielded, so connection events like `Event::Connected` are not
+    /// included.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut greetings = db.subscribe_key("greeting");
+    /// while let Some(event) = greetings.next().await {
+    ///     println!("Greeting changed: {:?}", event);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe_key(&self, key: &str) -> impl Stream<Item = Event> + Unpin {
+        let key = key.to_string();
+        self.event_stream()
+            .filter(move |event| future::ready(event.key() == Some(key.as_str())))
     }
+
+    /// Stream of events for all keys starting with `prefix`
+    /// 
+    /// Matching is a plain, case-sensitive string prefix check done
+    /// client-side. Connection events are not included.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut users = db.subscribe_prefix("user:");
+    /// while let Some(event) = users.next().await {
+    ///     println!("User changed: {:?}", event);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe_prefix(&self, prefix: &str) -> impl Stream<Item = Event> + Unpin {
+        let prefix = prefix.to_string();
+        self.event_stream()
+            .filter(move |event| future::ready(event.key().is_some_and(|key| key.starts_with(&prefix))))
+    }
+
+    /// Receives only the events for which `predicate` returns `true`
+    /// 
+    /// The predicate runs client-side on a background task, so the receiver is
+    /// only woken for matching events. The task stops once the returned
+    /// receiver is dropped. Matching events that arrive while the receiver is
+    /// full wait for room; events the task misses because it fell behind the
+    /// socket are skipped.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::Event;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut alerts = db.subscribe_filtered(|event| match event {
+    ///     Event::Set { key, value } => key == "temperature" && value.as_f64().is_some_and(|t| t > 30.0),
+    ///     _ => false,
+    /// });
+    /// while let Some(event) = alerts.recv().await {
+    ///     println!("Too hot: {:?}", event);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe_filtered<F>(&self, predicate: F) -> mpsc::Receiver<Event>
+    where
+        F: Fn(&Event) -> bool + Send + 'static,
+    {
+        let mut events = self.subscribe_typed();
+        let (tx, rx) = mpsc::channel(self.config.event_buffer_size);
+        let cancel = self.cancel.clone();
+        let forward = async move {
+            loop {
+                let event = tokio::select! {
+                    _ = tx.closed() => return,
+                    event = events.recv() => event.map(|envelope| envelope.event),
+                };
+                match event {
+                    Ok(event) if predicate(&event) => {
+                        if tx.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        };
+        tokio::spawn(async move { cancel.run_until_cancelled(forward).await });
+        rx
+    }
+
+    /// Receives at most one event per key every `window`, carrying its latest value
+    /// 
+    /// A background task buffers events from the first one after a quiet
+    /// period until `window` has passed, then delivers the most recent event
+    /// for each key that changed, in the order the keys first changed. A key
+    /// set several times and then deleted within a window only shows up as
+    /// `Event::Deleted`. Connection events are delivered right away, after
+    /// the events buffered before them. The task stops once the returned
+    /// receiver is dropped.
+    /// 
+    /// The window trades latency for volume: every change is delivered up to
+    /// `window` late, and in exchange a key updated a thousand times a second
+    /// costs one event per window instead of a thousand. Pick the longest
+    /// delay the consumer can tolerate, such as a UI's refresh rate. Because
+    /// intermediate values are dropped, don't use this for consumers that
+    /// need every change, like counters or audit logs.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut updates = db.subscribe_coalesced(Duration::from_millis(250));
+    /// while let Some(event) = updates.recv().await {
+    ///     println!("Redraw with {:?}", event);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe_coalesced(&self, window: std::time::Duration) -> mpsc::Receiver<Event> {
+        coalesce::spawn(self.subscribe_typed(), window, self.config.event_buffer_size, self.cancel.clone())
+    }
+
+    /// Waits until the value of `key` satisfies `predicate` and returns it
+    /// 
+    /// The current value is checked first, then every `set` event for the
+    /// key. The subscription starts before the current value is read, so a
+    /// change landing in between is still seen. A missing or deleted key
+    /// never matches. If events were missed, because the receiver lagged or
+    /// the socket reconnected, the value is read again. Fails with
+    /// `WireKVSError::Timeout` if no matching value shows up within `timeout`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.wait_for("job:42", |status| status == &json!("done"), Duration::from_secs(30))
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn wait_for(
+        &self,
+        key: &str,
+        predicate: impl Fn(&Value) -> bool,
+        timeout: std::time::Duration,
+    ) -> Result<Value, WireKVSError> {
+        check_key(key)?;
+        let mut events = self.events.subscribe_internal();
+        let wait = async {
+            let mut current = self.get_opt(key).await?;
+            loop {
+                if let Some(value) = current.take().filter(|value| predicate(value)) {
+                    return Ok(value);
+                }
+                current = match events.recv().await.map(|envelope| envelope.event) {
+                    Ok(Event::Set { key: changed, value }) if changed == key => Some(value),
+                    Ok(Event::Connected) | Err(RecvError::Lagged(_)) => self.get_opt(key).await?,
+                    Ok(_) => None,
+                    Err(RecvError::Closed) => return Err(WireKVSError::NotConnected),
+                };
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or(Err(WireKVSError::Timeout))
+    }
+}
+
+/// Rejects keys that can't be placed in a request URL
+fn check_key(key: &str) -> Result<(), WireKVSError> {
+    if matches!(key, "" | "." | "..") {
+        return Err(WireKVSError::InvalidKey);
+    }
+    Ok(())
+}
+
+/// Appends `segments` to the path of `base`, percent-encoding each one
+///
+/// The path of `base` is kept, with or without a trailing slash, so an API
+/// served under a subpath like `https://example.com/kvs/v2` works as is.
+/// Segments can't smuggle in extra path components or a query, and a base URL
+/// that doesn't parse is reported instead of panicking. `.` and `..` would be
+/// resolved away by URL normalization and are rejected, just like empty
+/// segments.
+fn build_url(base: &str, segments: &[&str]) -> Result<Url, WireKVSError> {
+    if let Some(segment) = segments.iter().find(|segment| matches!(**segment, "" | "." | "..")) {
+        return Err(WireKVSError::InvalidConfig(format!("invalid url path segment {:?}", segment)));
+    }
+    let mut url = Url::parse(base)?;
+    url.path_segments_mut()
+        .map_err(|_| WireKVSError::InvalidConfig(format!("{:?} can't be used as a base url", base)))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// Builds the event socket URL of database `id`: the access key first,
+/// unless it goes in a header, then the configured extra query parameters
+fn socket_url(config: &WireKVSConfig, id: &str, access_key: &str) -> Result<Url, WireKVSError> {
+    let mut url = build_url(&config.ws_base_url, &[id])?;
+    if config.ws_auth == SocketAuth::QueryParam {
+        url.query_pairs_mut().append_pair("accessKey", access_key);
+    }
+    if !config.ws_query_params.is_empty() {
+        url.query_pairs_mut().extend_pairs(&config.ws_query_params);
+    }
+    Ok(url)
+}
+
+/// Applies `patch` to `target` as described in RFC 7386
+fn merge_patch(target: Value, patch: &Value) -> Value {
+    let Value::Object(fields) = patch else {
+        return patch.clone();
+    };
+    let mut target = match target {
+        Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    for (name, value) in fields {
+        if value.is_null() {
+            target.remove(name);
+        } else {
+            let current = target.remove(name).unwrap_or(Value::Null);
+            target.insert(name.clone(), merge_patch(current, value));
+        }
+    }
+    Value::Object(target)
+}
+
+/// Elements of an array value, empty for a missing key
+fn array_items(value: Option<&Value>) -> Result<Vec<Value>, WireKVSError> {
+    Ok(expect_type(value.cloned(), "array", |value| match value {
+        Value::Array(items) => Some(items),
+        _ => None,
+    })?
+    .unwrap_or_default())
+}
+
+/// Reads the `ETag` header of a response, if it is valid text
+fn etag(response: &reqwest::Response) -> Option<String> {
+    let value = response.headers().get(reqwest::header::ETAG)?;
+    value.to_str().ok().map(str::to_string)
 }
 
 pub struct WireKVS {
-    token: String,
+    token: auth::Token,
+    client: reqwest::Client,
+    config: WireKVSConfig,
+    key_refresh: Option<auth::KeyRefresh>,
 }
 
 impl WireKVS {
     /// Creates a new WireKVS client instance
     /// 
+    /// Panics if the TLS backend can't be initialized, like
+    /// `reqwest::Client::new`; use `WireKVS::builder` to get an error instead.
+    /// 
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use wirekvs::WireKVS;
+    /// # async fn example() {
     /// let client = WireKVS::new("auth-token".to_string());
+    /// # }
     /// ```
     pub fn new(token: String) -> Self {
-        WireKVS { token }
+        Self::with_config(token, WireKVSConfig::default()).expect("failed to build HTTP client")
     }
 
-    /// Lists all databases for the authenticated user
+    /// Starts building a client with optional settings
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use wirekvs::WireKVS;
+    /// let client = WireKVS::builder()
+    ///     .token("auth-token")
+    ///     .base_url("http://localhost:8080/v2")
+    ///     .timeout(Duration::from_secs(10))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> WireKVSBuilder {
+        WireKVSBuilder::new()
+    }
+
+    /// Creates a new WireKVS client instance using custom connection settings
+    /// 
+    /// Databases opened through `database` inherit the same settings. Fails
+    /// with `WireKVSError::InvalidConfig` if the settings don't pass the same
+    /// checks as `WireKVSBuilder::build`.
     /// 
     /// # Example
+    /// ```no_run
+    /// # use wirekvs::{WireKVS, WireKVSConfig};
+    /// let config = WireKVSConfig {
+    ///     base_url: "http://localhost:8080/v2".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let client = WireKVS::with_config("auth-token".to_string(), config).unwrap();
     /// ```
+    pub fn with_config(token: String, config: WireKVSConfig) -> Result<Self, WireKVSError> {
+        config.validate()?;
+        let client = config.http_client()?;
+        Ok(Self::with_http_client(token, client, config))
+    }
+
+    /// Creates a new WireKVS client instance that sends requests through `client`
+    /// 
+    /// Databases opened through `database` share the same `reqwest::Client`.
+    /// As with `WireKVSDatabase::with_http_client`, the timeout, user agent and
+    /// default headers in `config` are not applied to a user-supplied client.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::{WireKVS, WireKVSConfig};
+    /// let http = reqwest::Client::builder().http2_prior_knowledge().build().unwrap();
+    /// let client = WireKVS::with_http_client("auth-token".to_string(), http, WireKVSConfig::default());
+    /// ```
+    pub fn with_http_client(token: String, client: reqwest::Client, config: WireKVSConfig) -> Self {
+        WireKVS {
+            token: auth::Token::new(token, None),
+            client,
+            config,
+            key_refresh: None,
+        }
+    }
+
+    /// Builds a management API URL from `segments`, see the module-level `build_url`
+    fn build_url(&self, segments: &[&str]) -> Result<Url, WireKVSError> {
+        build_url(&self.config.base_url, segments)
+    }
+
+    /// Lists all databases for the authenticated user
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
     /// let databases = client.list_databases().await.unwrap();
     /// println!("Databases: {:?}", databases);
+    /// # }
     /// ```
-    pub async fn list_databases(&self) -> Result<Value, reqwest::Error> {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&format!("{}/databases", API_BASE_URL))
-            .header("Authorization", &self.token)
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(response)
+    pub async fn list_databases(&self) -> Result<Value, WireKVSError> {
+        let url = self.build_url(&["databases"])?;
+        let request = |token: &str| self.client.get(url.clone()).header(AUTHORIZATION, token);
+        metrics::observe("list_databases", async {
+            let response = self.token.send(&self.config, true, request).await?;
+            parse_json(response, self.config.max_response_size).await
+        })
+        .await
+    }
+
+    /// Reads the current settings and metadata of a database
+    /// 
+    /// The public API doesn't document a detail endpoint, so this assumes the
+    /// server describes a database at `/databases/{id}` with the same field
+    /// names `create_database` sends, plus `name` and `createdAt`. See
+    /// `DatabaseInfo` for the defaults used for missing fields. A database
+    /// that doesn't exist fails with `WireKVSError::NotFound`, a body with
+    /// none of those fields with `WireKVSError::Decode`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let info = client.get_database_info("database-id").await.unwrap();
+    /// if info.config.allow_public_writes {
+    ///     println!("{} accepts public writes", info.name);
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_database_info(&self, id: &str) -> Result<DatabaseInfo, WireKVSError> {
+        let url = self.build_url(&["databases", id])?;
+        let request = |token: &str| self.client.get(url.clone()).header(AUTHORIZATION, token);
+        let body = metrics::observe("get_database_info", async {
+            let response = self.token.send(&self.config, true, request).await?;
+            parse_json(response, self.config.max_response_size).await
+        })
+        .await?;
+        DatabaseInfo::from_value(id, body)
+    }
+
+    /// Changes a database's access settings and returns the resulting config
+    /// 
+    /// Only the permissions set in `update` are sent, as a `PATCH` to
+    /// `/database/{id}`, so the others keep their current value. Pass a full
+    /// `DatabaseConfig` with `.into()` to set all four. If the server doesn't
+    /// echo the database back, its settings are read again with
+    /// `get_database_info` to confirm the change.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::DatabaseConfigUpdate;
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let update = DatabaseConfigUpdate {
+    ///     allow_public_writes: Some(false),
+    ///     ..Default::default()
+    /// };
+    /// let config = client.update_database_config("database-id", update).await.unwrap();
+    /// assert!(!config.allow_public_writes);
+    /// # }
+    /// ```
+    pub async fn update_database_config(
+        &self,
+        id: &str,
+        update: impl Into<DatabaseConfigUpdate>,
+    ) -> Result<DatabaseConfig, WireKVSError> {
+        let url = self.build_url(&["database", id])?;
+        let body = update.into().to_body();
+        let request = |token: &str| self.client.patch(url.clone()).header(AUTHORIZATION, token).json(&body);
+        let echoed = metrics::observe("update_database_config", async {
+            let response = self.token.send(&self.config, true, request).await?;
+            read_body(check_status(response).await?, self.config.max_response_size).await
+        })
+        .await?;
+        match serde_json::from_slice(&echoed) {
+            Ok(body) if DatabaseInfo::describes_config(&body) => Ok(DatabaseInfo::from_value(id, body)?.config),
+            _ => Ok(self.get_database_info(id).await?.config),
+        }
+    }
+
+    /// Checks that the backend is reachable and the token is accepted
+    /// 
+    /// Sends a `HEAD` request for the database list, so nothing is changed
+    /// and no body is transferred. A rejected token fails with
+    /// `WireKVSError::Unauthorized`.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// if let Err(e) = client.health_check().await {
+    ///     eprintln!("WireKVS unavailable: {}", e);
+    /// }
+    /// # }
+    /// ```
+    pub async fn health_check(&self) -> Result<(), WireKVSError> {
+        let url = self.build_url(&["databases"])?;
+        let request = |token: &str| self.client.head(url.clone()).header(AUTHORIZATION, token);
+        metrics::observe("health_check", async {
+            let response = self.token.send(&self.config, true, request).await?;
+            check_status(response).await
+        })
+        .await?;
+        Ok(())
     }
 
     /// Creates a new database with specified configuration
     /// 
+    /// Fails with `WireKVSError::Decode` if the response doesn't contain the
+    /// new database's id and access key.
+    /// 
     /// # Example
+    /// ```no_run
+    /// # use wirekvs::DatabaseConfig;
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let config = DatabaseConfig {
+    ///     allow_public_reads: true,
+    ///     ..Default::default()
+    /// };
+    /// let created = client.create_database("My Database", config).await.unwrap();
+    /// println!("Created {} with access key {}", created.id, created.access_key);
+    /// # }
     /// ```
-    /// let mut config = HashMap::new();
-    /// config.insert("allowPublicReads".to_string(), true);
-    /// let db = client.create_database("My Database", config).await.unwrap();
+    pub async fn create_database(&self, name: &str, config: DatabaseConfig) -> Result<CreatedDatabase, WireKVSError> {
+        let url = self.build_url(&["database"])?;
+        let body = json!({
+            "name": name,
+            "allowPublicWrites": config.allow_public_writes,
+            "allowPublicReads": config.allow_public_reads,
+            "allowPublicModifications": config.allow_public_modifications,
+            "allowSpecificPublicReads": config.allow_specific_public_reads,
+        });
+        let request = |token: &str| self.client.post(url.clone()).header(AUTHORIZATION, token).json(&body);
+        let mut created: CreatedDatabase = metrics::observe("create_database", async {
+            let response = self.token.send(&self.config, false, request).await?;
+            from_json(parse_json(response, self.config.max_response_size).await?)
+        })
+        .await?;
+        if created.name.is_empty() {
+            created.name = name.to_string();
+        }
+        Ok(created)
+    }
+
+    /// Creates a new database and connects to it
+    /// 
+    /// If the database is created but connecting to it fails, the new
+    /// database is deleted again on a best-effort basis and the connection
+    /// error is returned, so a failed call doesn't leave an orphaned database
+    /// behind.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use serde_json::json;
+    /// # use wirekvs::DatabaseConfig;
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let db = client.create_and_connect("My Database", DatabaseConfig::default()).await.unwrap();
+    /// db.set("key", json!("value")).await.unwrap();
+    /// # }
     /// ```
-    pub async fn create_database(&self, name: &str, config: HashMap<String, bool>) -> Result<Value, reqwest::Error> {
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&format!("{}/database", API_BASE_URL))
-            .header("Authorization", &self.token)
-            .json(&json!({
-                "name": name,
-                "allowPublicWrites": config.get("allowPublicWrites").unwrap_or(&false),
-                "allowPublicReads": config.get("allowPublicReads").unwrap_or(&false),
-                "allowPublicModifications": config.get("allowPublicModifications").unwrap_or(&false),
-                "allowSpecificPublicReads": config.get("allowSpecificPublicReads").unwrap_or(&false),
-            }))
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(response)
+    pub async fn create_and_connect(&self, name: &str, config: DatabaseConfig) -> Result<WireKVSDatabase, WireKVSError> {
+        let created = self.create_database(name, config).await?;
+        match self.connect(&created).await {
+            Ok(db) => Ok(db),
+            Err(err) => {
+                debug!(db = %created.id, error = %err, "connecting to new database failed, deleting it");
+                let _ = self.delete_database(&created.id).await;
+                Err(err)
+            }
+        }
     }
 
     /// Deletes a database by ID
     /// 
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
     /// client.delete_database("database-id").await.unwrap();
+    /// # }
     /// ```
-    pub async fn delete_database(&self, id: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-        client
-            .delete(&format!("{}/database/{}", API_BASE_URL, id))
-            .header("Authorization", &self.token)
-            .send()
-            .await?;
+    pub async fn delete_database(&self, id: &str) -> Result<(), WireKVSError> {
+        let url = self.build_url(&["database", id])?;
+        let request = |token: &str| self.client.delete(url.clone()).header(AUTHORIZATION, token);
+        metrics::observe("delete_database", async {
+            let response = self.token.send(&self.config, true, request).await?;
+            check_status(response).await
+        })
+        .await?;
         Ok(())
     }
 
     /// Gets a database instance for direct operations
     /// 
+    /// The returned handle shares this client's HTTP connection pool.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let db = client.database("database-id".to_string(), "access-key".to_string()).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn database(&self, id: String, access_key: String) -> Result<WireKVSDatabase, WireKVSError> {
+        let refresh = self.key_refresh.as_ref().map(|refresh| refresh.for_database(&id));
+        WireKVSDatabase::open(id, access_key, self.client.clone(), self.config.clone(), refresh).await
+    }
+
+    /// Connects to several databases concurrently, given as `(id, access_key)` pairs
+    /// 
+    /// Each handle opens its own event socket. The handshakes run in
+    /// parallel, with at most `WireKVSConfig::batch_concurrency` (8 by
+    /// default) in flight at a time, and the handles come back in the order
+    /// of `creds`. Every database is attempted; if any fails, the failures are
+    /// returned together as `WireKVSError::Batch`, listing the id each error
+    /// belongs to. The databases that did connect are shut down with
+    /// `WireKVSDatabase::shutdown` before the error is returned, so their
+    /// close handshakes have finished by then.
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let dbs = client
+    ///     .connect_many(vec![
+    ///         ("users-id".to_string(), "users-key".to_string()),
+    ///         ("orders-id".to_string(), "orders-key".to_string()),
+    ///     ])
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn connect_many(&self, creds: Vec<(String, String)>) -> Result<Vec<WireKVSDatabase>, WireKVSError> {
+        let results: Vec<_> = stream::iter(creds)
+            .map(|(id, access_key)| async move {
+                let result = self.database(id.clone(), access_key).await;
+                (id, result)
+            })
+            .buffered(self.config.batch_concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut databases = Vec::with_capacity(results.len());
+        let mut failures = Vec::new();
+        for (id, result) in results {
+            match result {
+                Ok(db) => databases.push(db),
+                Err(e) => failures.push((id, e)),
+            }
+        }
+        if !failures.is_empty() {
+            future::join_all(databases.iter().map(WireKVSDatabase::shutdown)).await;
+        }
+        batch_result(failures)?;
+        Ok(databases)
+    }
+
+    /// Merges the events of several databases into one stream, each tagged
+    /// with the id of the database it came from
+    /// 
+    /// Every handle keeps its own event socket; this only saves spawning a
+    /// forwarding task per database. Events of one database stay in order,
+    /// while events of different databases are interleaved as they arrive.
+    /// Like `WireKVSDatabase::event_stream`, a database whose stream falls
+    /// behind skips the events it missed. The stream ends once the streams
+    /// of all `databases` have ended.
+    /// 
     /// # Example
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # async fn example(client: wirekvs::WireKVS, users: wirekvs::WireKVSDatabase, orders: wirekvs::WireKVSDatabase) {
+    /// let mut events = client.multiplex_events(&[&users, &orders]);
+    /// while let Some((id, event)) = events.next().await {
+    ///     println!("{}: {:?}", id, event);
+    /// }
+    /// # }
     /// ```
-    /// let db = client.database("database-id".to_string(), "access-key".to_string()).await;
+    pub fn multiplex_events(&self, databases: &[&WireKVSDatabase]) -> impl Stream<Item = (String, Event)> + Unpin {
+        stream::select_all(databases.iter().map(|db| {
+            let id = db.id().to_string();
+            db.event_stream().map(move |event| (id.clone(), event))
+        }))
+    }
+
+    /// Gets a database instance for a database returned by `create_database`
+    /// 
+    /// # Example
+    /// ```no_run
+    /// # use wirekvs::DatabaseConfig;
+    /// # async fn example(client: wirekvs::WireKVS) {
+    /// let created = client.create_database("My Database", DatabaseConfig::default()).await.unwrap();
+    /// let db = client.connect(&created).await.unwrap();
+    /// # }
     /// ```
-    pub async fn database(&self, id: String, access_key: String) -> WireKVSDatabase {
-        WireKVSDatabase::new(id, access_key).await
+    pub async fn connect(&self, database: &CreatedDatabase) -> Result<WireKVSDatabase, WireKVSError> {
+        self.database(database.id.clone(), database.access_key.clone()).await
     }
 } 
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+use crate::error::WireKVSError;
+
+/// Client-side token bucket limiting how fast requests are sent
+///
+/// The bucket holds up to `burst` tokens and refills at `requests_per_second`.
+/// Every HTTP request, including each retry, takes one token. When the bucket
+/// is empty, the request waits for the next token instead of failing. With
+/// `max_wait` set, requests that would have to wait longer fail with
+/// `WireKVSError::RateLimited` instead.
+///
+/// Clones share the same bucket. Since `WireKVS::database` hands its config to
+/// every database it opens, one limiter set on the client covers all of them.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use wirekvs::{RateLimiter, WireKVS};
+///
+/// let client = WireKVS::builder()
+///     .token("auth-token")
+///     .rate_limit(RateLimiter::new(10.0, 20).max_wait(Duration::from_secs(2)))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: u32,
+    max_wait: Option<Duration>,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// Allows `requests_per_second` on average, with bursts of up to `burst` requests
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        RateLimiter {
+            requests_per_second,
+            burst,
+            max_wait: None,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: f64::from(burst),
+                refilled: Instant::now(),
+            })),
+        }
+    }
+
+    /// Fails requests with `WireKVSError::RateLimited` instead of waiting longer than `max_wait`
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), WireKVSError> {
+        if !(self.requests_per_second.is_finite() && self.requests_per_second > 0.0) {
+            return Err(WireKVSError::InvalidConfig("rate limit must be a positive number".to_string()));
+        }
+        if self.burst == 0 {
+            return Err(WireKVSError::InvalidConfig("rate limit burst must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Waits until a request may be sent
+    ///
+    /// A token is reserved up front, so concurrent callers are served in the
+    /// order they arrived instead of racing for each refill.
+    pub(crate) async fn acquire(&self) -> Result<(), WireKVSError> {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.requests_per_second;
+            bucket.tokens = (bucket.tokens + refill).min(f64::from(self.burst));
+            bucket.refilled = now;
+
+            bucket.tokens -= 1.0;
+            let wait = Duration::from_secs_f64((-bucket.tokens).max(0.0) / self.requests_per_second);
+            if self.max_wait.is_some_and(|max_wait| wait > max_wait) {
+                bucket.tokens += 1.0;
+                return Err(WireKVSError::RateLimited { retry_after: wait });
+            }
+            wait
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+}
+
+/// Client-side cap on how many HTTP requests are in flight at once
+///
+/// Every HTTP attempt, including each retry, holds one permit from when it is
+/// sent until its response headers arrive; reading the body and waiting out a
+/// retry backoff don't count. Requests beyond the limit queue up in the order
+/// they arrived. This bounds the load of the client as a whole, independent
+/// of per-call settings like `WireKVSConfig::batch_concurrency`. Event
+/// sockets aren't counted.
+///
+/// A request takes its permit before its `RateLimiter` token, so requests
+/// queued here don't use up the rate limiter's burst while they wait. With
+/// both set, a request therefore waits for a free slot first and a token
+/// second.
+///
+/// Clones share the same permits, so one limit set on the client covers every
+/// database it opens.
+///
+/// # Example
+/// ```
+/// use wirekvs::{ConcurrencyLimit, WireKVS};
+///
+/// let client = WireKVS::builder()
+///     .token("auth-token")
+///     .concurrency_limit(ConcurrencyLimit::new(16))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    max_concurrent: usize,
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    /// Allows at most `max_concurrent` requests in flight at once
+    pub fn new(max_concurrent: usize) -> Self {
+        ConcurrencyLimit {
+            max_concurrent,
+            permits: Arc::new(Semaphore::new(max_concurrent.min(Semaphore::MAX_PERMITS))),
+        }
+    }
+
+    /// The most requests allowed in flight at once
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), WireKVSError> {
+        if self.max_concurrent == 0 {
+            return Err(WireKVSError::InvalidConfig("concurrency limit must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Waits until a request may be sent, returning the permit to hold while it is in flight
+    pub(crate) async fn acquire(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed
+        self.permits.acquire().await.expect("concurrency limit semaphore closed")
+    }
+}
@@ -1,8 +1,6 @@
 use serde_json::json;
-use std::collections::HashMap;
 use std::time::Duration;
-use tokio;
-use wirekvs::{WireKVS, WireKVSDatabase};
+use wirekvs::{format_event, DatabaseConfig, WireKVS, WireKVSDatabase};
 
 const AUTH_TOKEN: &str = "your-token-from-cookies-here";
 
@@ -15,13 +13,13 @@ const AUTH_TOKEN: &str = "your-token-from-cookies-here";
 async fn demo_direct_connect() {
     println!("🚀 Starting WireKVS Direct Connect Demo");
 
-    let db = WireKVSDatabase::new("your-database-id".to_string(), "your-access-key".to_string()).await;
+    let db = WireKVSDatabase::new("your-database-id".to_string(), "your-access-key".to_string()).await.unwrap();
     println!("✅ Connected directly to database");
 
     let mut rx = db.subscribe();
     tokio::spawn(async move {
         while let Ok(event) = rx.recv().await {
-            println!("📝 Received event: {:?}", event);
+            println!("📝 Received event: {}", format_event(&event, true));
         }
     });
 
@@ -49,23 +47,22 @@ async fn demo_with_client() {
     println!("✅ Client initialized");
 
     println!("\n🆕 Creating new database...");
-    let mut config = HashMap::new();
-    config.insert("allowPublicReads".to_string(), true);
-    config.insert("allowPublicWrites".to_string(), false);
-    config.insert("allowPublicModifications".to_string(), false);
-    config.insert("allowSpecificPublicReads".to_string(), true);
+    let config = DatabaseConfig {
+        allow_public_reads: true,
+        allow_specific_public_reads: true,
+        ..Default::default()
+    };
 
     let new_db = client.create_database("Demo Database", config).await.unwrap();
     println!("Created database: {:?}", new_db);
 
-    let db = client.database(new_db["kvsId"].as_str().unwrap().to_string(), 
-                           new_db["accessKey"].as_str().unwrap().to_string()).await;
+    let db = client.connect(&new_db).await.unwrap();
     println!("\n🔌 Connected to database");
 
     let mut rx = db.subscribe();
     tokio::spawn(async move {
         while let Ok(event) = rx.recv().await {
-            println!("📝 Received event: {:?}", event);
+            println!("📝 Received event: {}", format_event(&event, true));
         }
     });
 
@@ -88,7 +85,7 @@ async fn demo_with_client() {
     db.delete("number").await.unwrap();
 
     println!("\n🧹 Cleaning up...");
-    client.delete_database(&new_db["kvsId"].as_str().unwrap()).await.unwrap();
+    client.delete_database(&new_db.id).await.unwrap();
 
     println!("\n✅ Client demo completed successfully!");
 }
@@ -0,0 +1,381 @@
+//! In-memory stand-in for a database, enabled by the `in-memory` feature
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::error::{expect_type, from_json, json_type};
+use crate::event::Broadcaster;
+use crate::{check_key, merge_patch, Event, EventEnvelope, WireKVSError};
+
+/// Database kept in a local map, for testing code without a server
+///
+/// Offers the data and event methods of `WireKVSDatabase` with the same
+/// signatures and errors, so test code calls it the same way. Nothing is sent
+/// over the network: writes go to an in-process map and fire the same
+/// `set`/`delete` events the server would send through `subscribe`.
+///
+/// Unlike the real backend, every operation is atomic, so `compare_and_set`,
+/// `increment` and `update` never lose writes or fail with
+/// `WireKVSError::Conflict`. Deleting a missing key succeeds without firing an
+/// event. Clones share the same entries and subscribers.
+///
+/// # Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use serde_json::json;
+/// use wirekvs::InMemoryDatabase;
+///
+/// let db = InMemoryDatabase::new();
+/// db.set("greeting", json!("Hello!")).await.unwrap();
+/// assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct InMemoryDatabase {
+    entries: Arc<Mutex<BTreeMap<String, Value>>>,
+    events: Broadcaster,
+}
+
+impl Default for InMemoryDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryDatabase {
+    /// Creates an empty database that buffers 100 events per subscriber
+    pub fn new() -> Self {
+        Self::with_event_buffer_size(100)
+    }
+
+    /// Creates an empty database that buffers `size` events per subscriber
+    pub fn with_event_buffer_size(size: usize) -> Self {
+        InMemoryDatabase {
+            entries: Arc::new(Mutex::new(BTreeMap::new())),
+            events: Broadcaster::new(size.max(1)),
+        }
+    }
+
+    /// Gets all entries as a JSON object
+    pub async fn get_all_entries(&self) -> Result<Value, WireKVSError> {
+        let entries = self.entries.lock().unwrap();
+        Ok(Value::Object(entries.clone().into_iter().collect()))
+    }
+
+    /// Lists every key, in lexicographic order
+    pub async fn keys(&self) -> Result<Vec<String>, WireKVSError> {
+        Ok(self.entries.lock().unwrap().keys().cloned().collect())
+    }
+
+    /// Lists the keys that start with `prefix`, in lexicographic order
+    pub async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, WireKVSError> {
+        let entries = self.entries.lock().unwrap();
+        Ok(entries.keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+    }
+
+    /// Gets a value by key, failing with `WireKVSError::NotFound` if it doesn't exist
+    pub async fn get(&self, key: &str) -> Result<Value, WireKVSError> {
+        check_key(key)?;
+        self.entries.lock().unwrap().get(key).cloned().ok_or(WireKVSError::NotFound)
+    }
+
+    /// Gets a value by key, or `None` if the key doesn't exist
+    pub async fn get_opt(&self, key: &str) -> Result<Option<Value>, WireKVSError> {
+        check_key(key)?;
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    /// Checks whether a key exists
+    pub async fn exists(&self, key: &str) -> Result<bool, WireKVSError> {
+        check_key(key)?;
+        Ok(self.entries.lock().unwrap().contains_key(key))
+    }
+
+    /// Gets a value by key and deserializes it into `T`
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, WireKVSError> {
+        from_json(self.get(key).await?)
+    }
+
+    /// Gets a string value, or `None` if the key doesn't exist
+    pub async fn get_string(&self, key: &str) -> Result<Option<String>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "string", |value| match value {
+            Value::String(text) => Some(text),
+            _ => None,
+        })
+    }
+
+    /// Gets an integer value, or `None` if the key doesn't exist
+    pub async fn get_i64(&self, key: &str) -> Result<Option<i64>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "integer", |value| value.as_i64())
+    }
+
+    /// Gets a number as a float, or `None` if the key doesn't exist
+    pub async fn get_f64(&self, key: &str) -> Result<Option<f64>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "number", |value| value.as_f64())
+    }
+
+    /// Gets a bool value, or `None` if the key doesn't exist
+    pub async fn get_bool(&self, key: &str) -> Result<Option<bool>, WireKVSError> {
+        expect_type(self.get_opt(key).await?, "bool", |value| value.as_bool())
+    }
+
+    /// Gets many keys at once, leaving missing keys out of the map
+    pub async fn get_many(&self, keys: &[&str]) -> Result<HashMap<String, Value>, WireKVSError> {
+        keys.iter().try_for_each(|key| check_key(key))?;
+        let entries = self.entries.lock().unwrap();
+        Ok(keys
+            .iter()
+            .filter_map(|key| entries.get(*key).map(|value| (key.to_string(), value.clone())))
+            .collect())
+    }
+
+    /// Sets a value for a specific key
+    pub async fn set(&self, key: &str, value: Value) -> Result<(), WireKVSError> {
+        check_key(key)?;
+        self.store(key, value);
+        Ok(())
+    }
+
+    /// Serializes `value` and sets it for a specific key
+    pub async fn set_typed<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), WireKVSError> {
+        self.set(key, serde_json::to_value(value)?).await
+    }
+
+    /// Sets a value given as already serialized JSON
+    pub async fn set_raw(&self, key: &str, json: &str) -> Result<(), WireKVSError> {
+        let value = serde_json::from_str(json)?;
+        self.set(key, value).await
+    }
+
+    /// Sets many keys at once
+    pub async fn set_many(&self, entries: HashMap<String, Value>) -> Result<(), WireKVSError> {
+        entries.keys().try_for_each(|key| check_key(key))?;
+        for (key, value) in entries {
+            self.store(&key, value);
+        }
+        Ok(())
+    }
+
+    /// Sets `new` only if the current value equals `expected`, `None` meaning absent
+    pub async fn compare_and_set(&self, key: &str, expected: Option<Value>, new: Value) -> Result<bool, WireKVSError> {
+        check_key(key)?;
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(key) != expected.as_ref() {
+            return Ok(false);
+        }
+        entries.insert(key.to_string(), new.clone());
+        self.events.send(json!({ "type": "set", "key": key, "value": new }));
+        Ok(true)
+    }
+
+    /// Sets a value only if the key doesn't exist yet, returning whether it was written
+    pub async fn set_if_absent(&self, key: &str, value: Value) -> Result<bool, WireKVSError> {
+        self.compare_and_set(key, None, value).await
+    }
+
+    /// Adds `delta` to an integer value and returns the new total
+    pub async fn increment(&self, key: &str, delta: i64) -> Result<i64, WireKVSError> {
+        check_key(key)?;
+        let mut entries = self.entries.lock().unwrap();
+        let total = match entries.get(key) {
+            None => delta,
+            Some(value) => match value.as_i64() {
+                Some(n) => n.saturating_add(delta),
+                None => {
+                    return Err(WireKVSError::TypeMismatch {
+                        expected: "integer",
+                        found: json_type(value),
+                    })
+                }
+            },
+        };
+        entries.insert(key.to_string(), json!(total));
+        self.events.send(json!({ "type": "set", "key": key, "value": total }));
+        Ok(total)
+    }
+
+    /// Appends `element` to an array value and returns the resulting array
+    pub async fn array_append(&self, key: &str, element: Value) -> Result<Value, WireKVSError> {
+        check_key(key)?;
+        let mut entries = self.entries.lock().unwrap();
+        let mut items = expect_type(entries.get(key).cloned(), "array", |value| match value {
+            Value::Array(items) => Some(items),
+            _ => None,
+        })?
+        .unwrap_or_default();
+        items.push(element);
+        let new = Value::Array(items);
+        entries.insert(key.to_string(), new.clone());
+        self.events.send(json!({ "type": "set", "key": key, "value": new }));
+        Ok(new)
+    }
+
+    /// Removes every element equal to `element` from an array value and
+    /// returns the resulting array
+    pub async fn array_remove(&self, key: &str, element: &Value) -> Result<Value, WireKVSError> {
+        check_key(key)?;
+        let mut entries = self.entries.lock().unwrap();
+        let items = expect_type(entries.get(key).cloned(), "array", |value| match value {
+            Value::Array(items) => Some(items),
+            _ => None,
+        })?
+        .unwrap_or_default();
+        let len = items.len();
+        let new = Value::Array(items.into_iter().filter(|item| item != element).collect());
+        if new.as_array().is_some_and(|items| items.len() != len) {
+            entries.insert(key.to_string(), new.clone());
+            self.events.send(json!({ "type": "set", "key": key, "value": new }));
+        }
+        Ok(new)
+    }
+
+    /// Replaces a value with the result of `f` applied to the current value
+    pub async fn update<F>(&self, key: &str, mut f: F) -> Result<Value, WireKVSError>
+    where
+        F: FnMut(Option<Value>) -> Value,
+    {
+        check_key(key)?;
+        let mut entries = self.entries.lock().unwrap();
+        let new = f(entries.get(key).cloned());
+        entries.insert(key.to_string(), new.clone());
+        self.events.send(json!({ "type": "set", "key": key, "value": new }));
+        Ok(new)
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to a value and returns the result
+    pub async fn patch(&self, key: &str, patch: Value) -> Result<Value, WireKVSError> {
+        self.update(key, |current| merge_patch(current.unwrap_or(Value::Null), &patch))
+            .await
+    }
+
+    /// Deletes a value by key
+    pub async fn delete(&self, key: &str) -> Result<(), WireKVSError> {
+        check_key(key)?;
+        self.remove(key);
+        Ok(())
+    }
+
+    /// Moves the value stored under `from` to `to`, replacing `to` only if `overwrite`
+    pub async fn rename(&self, from: &str, to: &str, overwrite: bool) -> Result<(), WireKVSError> {
+        check_key(from)?;
+        check_key(to)?;
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(from) {
+            return Err(WireKVSError::NotFound);
+        }
+        if from == to {
+            return Ok(());
+        }
+        if !overwrite && entries.contains_key(to) {
+            return Err(WireKVSError::AlreadyExists { key: to.to_string() });
+        }
+        let value = entries.remove(from).unwrap_or_default();
+        entries.insert(to.to_string(), value.clone());
+        self.events.send(json!({ "type": "set", "key": to, "value": value }));
+        self.events.send(json!({ "type": "delete", "key": from }));
+        Ok(())
+    }
+
+    /// Deletes many keys at once
+    pub async fn delete_many(&self, keys: &[&str]) -> Result<(), WireKVSError> {
+        keys.iter().try_for_each(|key| check_key(key))?;
+        for key in keys {
+            self.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Deletes every key, firing one delete event per key
+    pub async fn clear(&self) -> Result<(), WireKVSError> {
+        let mut entries = self.entries.lock().unwrap();
+        for key in std::mem::take(&mut *entries).keys() {
+            self.events.send(json!({ "type": "delete", "key": key }));
+        }
+        Ok(())
+    }
+
+    /// Subscribe to the raw JSON events fired by writes
+    pub fn subscribe(&self) -> broadcast::Receiver<Value> {
+        self.events.subscribe_raw()
+    }
+
+    /// Subscribe to the events fired by writes, parsed into `Event` and
+    /// paired with their raw JSON
+    pub fn subscribe_typed(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.events.subscribe_typed()
+    }
+
+    /// Stream of typed events that skips events a slow consumer misses
+    pub fn event_stream(&self) -> impl Stream<Item = Event> + Unpin {
+        BroadcastStream::new(self.subscribe_typed())
+            .filter_map(|envelope| future::ready(envelope.ok().map(|envelope| envelope.event)))
+    }
+
+    /// Stream of events for a single key
+    pub fn subscribe_key(&self, key: &str) -> impl Stream<Item = Event> + Unpin {
+        let key = key.to_string();
+        self.event_stream()
+            .filter(move |event| future::ready(event.key() == Some(key.as_str())))
+    }
+
+    /// Stream of events for all keys starting with `prefix`
+    pub fn subscribe_prefix(&self, prefix: &str) -> impl Stream<Item = Event> + Unpin {
+        let prefix = prefix.to_string();
+        self.event_stream()
+            .filter(move |event| future::ready(event.key().is_some_and(|key| key.starts_with(&prefix))))
+    }
+
+    /// Waits until the value of `key` satisfies `predicate` and returns it
+    pub async fn wait_for(
+        &self,
+        key: &str,
+        predicate: impl Fn(&Value) -> bool,
+        timeout: Duration,
+    ) -> Result<Value, WireKVSError> {
+        check_key(key)?;
+        let mut events = self.events.subscribe_internal();
+        let wait = async {
+            let mut current = self.get_opt(key).await?;
+            loop {
+                if let Some(value) = current.take().filter(|value| predicate(value)) {
+                    return Ok(value);
+                }
+                current = match events.recv().await.map(|envelope| envelope.event) {
+                    Ok(Event::Set { key: changed, value }) if changed == key => Some(value),
+                    Err(RecvError::Lagged(_)) => self.get_opt(key).await?,
+                    Ok(_) => None,
+                    Err(RecvError::Closed) => return Err(WireKVSError::NotConnected),
+                };
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or(Err(WireKVSError::Timeout))
+    }
+
+    // Events are sent while the lock is held so they arrive in write order
+
+    fn store(&self, key: &str, value: Value) {
+        let event = json!({ "type": "set", "key": key, "value": value });
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), value);
+        self.events.send(event);
+    }
+
+    fn remove(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(key).is_some() {
+            self.events.send(json!({ "type": "delete", "key": key }));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Request and event metrics, recorded through the `metrics` crate when the
+//! `metrics` feature is enabled and compiled to nothing otherwise
+//!
+//! Recorded series:
+//! - `wirekvs_requests_total`, counter labelled by `operation`
+//! - `wirekvs_request_duration_seconds`, histogram labelled by `operation`
+//! - `wirekvs_errors_total`, counter labelled by `operation` and `error`
+//! - `wirekvs_reconnects_total`, counter of successful event socket reconnects
+//! - `wirekvs_events_total`, counter of events delivered to subscribers
+
+use std::future::Future;
+
+use crate::error::WireKVSError;
+
+/// Runs one API request, recording its count, latency and outcome under `operation`
+pub(crate) async fn observe<T, F>(operation: &'static str, request: F) -> Result<T, WireKVSError>
+where
+    F: Future<Output = Result<T, WireKVSError>>,
+{
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let result = request.await;
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!("wirekvs_requests_total", "operation" => operation).increment(1);
+        ::metrics::histogram!("wirekvs_request_duration_seconds", "operation" => operation)
+            .record(started.elapsed().as_secs_f64());
+        if let Err(e) = &result {
+            ::metrics::counter!("wirekvs_errors_total", "operation" => operation, "error" => e.kind()).increment(1);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = operation;
+    result
+}
+
+/// Counts a successful event socket reconnect
+pub(crate) fn reconnected() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("wirekvs_reconnects_total").increment(1);
+}
+
+/// Counts an event delivered to subscribers
+pub(crate) fn event_delivered() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("wirekvs_events_total").increment(1);
+}
@@ -0,0 +1,125 @@
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+use serde_json::{Map, Value};
+
+use crate::{check_key, page, Event, WireKVSDatabase, WireKVSError};
+
+/// View of the keys of a database that start with a prefix, see `WireKVSDatabase::namespace`
+///
+/// Keys passed to the view are relative: `get("profile")` on the `user:123:`
+/// namespace reads `user:123:profile`. Keys the view reports, from `keys`,
+/// `get_all_entries` and events, have the prefix stripped again. The prefix
+/// is applied verbatim, so include the separator, and matching is a plain,
+/// case-sensitive string prefix check done client-side.
+///
+/// The view is as cheap to clone as the database handle it wraps and shares
+/// its connection, socket and cache.
+///
+/// # Example
+/// ```no_run
+/// # use serde_json::json;
+/// # async fn example(db: wirekvs::WireKVSDatabase) {
+/// let user = db.namespace("user:123:");
+/// user.set("profile", json!({ "name": "Ferris" })).await.unwrap();
+/// assert_eq!(user.keys().await.unwrap(), ["profile"]);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct NamespacedDatabase {
+    db: WireKVSDatabase,
+    prefix: String,
+}
+
+impl NamespacedDatabase {
+    pub(crate) fn new(db: WireKVSDatabase, prefix: String) -> Self {
+        NamespacedDatabase { db, prefix }
+    }
+
+    /// The prefix prepended to every key
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The underlying database, for operations that take absolute keys
+    pub fn database(&self) -> &WireKVSDatabase {
+        &self.db
+    }
+
+    /// Narrows the view to the keys that also start with `prefix` after this one
+    pub fn namespace(&self, prefix: &str) -> NamespacedDatabase {
+        NamespacedDatabase::new(self.db.clone(), format!("{}{}", self.prefix, prefix))
+    }
+
+    /// Gets a value by key, failing with `WireKVSError::NotFound` if it doesn't exist
+    pub async fn get(&self, key: &str) -> Result<Value, WireKVSError> {
+        self.db.get(&self.full_key(key)?).await
+    }
+
+    /// Gets a value by key, or `None` if the key doesn't exist
+    pub async fn get_opt(&self, key: &str) -> Result<Option<Value>, WireKVSError> {
+        self.db.get_opt(&self.full_key(key)?).await
+    }
+
+    /// Checks whether a key exists
+    pub async fn exists(&self, key: &str) -> Result<bool, WireKVSError> {
+        self.db.exists(&self.full_key(key)?).await
+    }
+
+    /// Sets a value for a specific key
+    pub async fn set(&self, key: &str, value: Value) -> Result<(), WireKVSError> {
+        self.db.set(&self.full_key(key)?, value).await
+    }
+
+    /// Deletes a value by key
+    pub async fn delete(&self, key: &str) -> Result<(), WireKVSError> {
+        self.db.delete(&self.full_key(key)?).await
+    }
+
+    /// Lists the keys in the namespace, in lexicographic order
+    ///
+    /// Like `WireKVSDatabase::keys`, this downloads all entries of the
+    /// database and filters them locally.
+    pub async fn keys(&self) -> Result<Vec<String>, WireKVSError> {
+        let keys = self.db.keys_with_prefix(&self.prefix).await?;
+        Ok(keys.iter().filter_map(|key| relative_key(&self.prefix, key)).map(str::to_string).collect())
+    }
+
+    /// Gets all entries in the namespace as a JSON object
+    pub async fn get_all_entries(&self) -> Result<Value, WireKVSError> {
+        let entries = page::entries_from_value(self.db.get_all_entries().await?)?;
+        let scoped: Map<String, Value> = entries
+            .into_iter()
+            .filter_map(|(key, value)| Some((relative_key(&self.prefix, &key)?.to_string(), value)))
+            .collect();
+        Ok(Value::Object(scoped))
+    }
+
+    /// Stream of events for keys in the namespace, with the prefix stripped
+    ///
+    /// Connection events are not included, just like with
+    /// `WireKVSDatabase::subscribe_prefix`.
+    pub fn subscribe(&self) -> impl Stream<Item = Event> + Unpin {
+        let prefix = self.prefix.clone();
+        self.db.event_stream().filter_map(move |event| {
+            future::ready(match event {
+                Event::Set { key, value } => relative_key(&prefix, &key).map(|key| Event::Set {
+                    key: key.to_string(),
+                    value,
+                }),
+                Event::Deleted { key } => relative_key(&prefix, &key).map(|key| Event::Deleted { key: key.to_string() }),
+                Event::Expired { key } => relative_key(&prefix, &key).map(|key| Event::Expired { key: key.to_string() }),
+                _ => None,
+            })
+        })
+    }
+
+    fn full_key(&self, key: &str) -> Result<String, WireKVSError> {
+        check_key(key)?;
+        Ok(format!("{}{}", self.prefix, key))
+    }
+}
+
+/// Strips `prefix`, skipping keys outside the namespace and the bare prefix itself
+fn relative_key<'a>(prefix: &str, key: &'a str) -> Option<&'a str> {
+    key.strip_prefix(prefix).filter(|key| !key.is_empty())
+}
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+
+use serde::de::Error as _;
+use serde_json::{Map, Value};
+
+use crate::error::{decode_error, WireKVSError};
+
+/// One page of database entries
+///
+/// A paginated response is expected to look like
+/// `{ "entries": ..., "nextCursor": "..." }`, where `nextCursor` is `null` on
+/// the last page. Servers that ignore the pagination parameters return the
+/// whole database instead, which is treated as a single final page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// The key/value pairs on this page
+    pub entries: Vec<(String, Value)>,
+    /// Cursor to pass to the next `get_entries_page` call, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl Page {
+    pub(crate) fn from_value(body: Value) -> Result<Page, WireKVSError> {
+        match body {
+            Value::Object(mut map) if map.contains_key("entries") && map.contains_key("nextCursor") => {
+                let next_cursor = map.remove("nextCursor").and_then(|c| c.as_str().map(str::to_string));
+                let entries = entries_from_value(map.remove("entries").unwrap_or(Value::Null))?;
+                Ok(Page { entries, next_cursor })
+            }
+            body => Ok(Page {
+                entries: entries_from_value(body)?,
+                next_cursor: None,
+            }),
+        }
+    }
+}
+
+/// Entries requested per page by `WireKVSDatabase::entries_stream`
+pub(crate) const WALK_PAGE_SIZE: usize = 100;
+
+/// Page failures in a row after which `entries_stream` gives up
+const WALK_MAX_FAILURES: u32 = 3;
+
+/// Progress of `WireKVSDatabase::entries_stream` through the pages
+pub(crate) struct Walk {
+    /// Cursor of the next page to fetch, `None` once the last page was fetched
+    pub(crate) cursor: Option<Option<String>>,
+    /// Entries of the current page that haven't been yielded yet
+    pub(crate) buffered: VecDeque<(String, Value)>,
+    failures: u32,
+}
+
+impl Walk {
+    pub(crate) fn new() -> Self {
+        Walk {
+            cursor: Some(None),
+            buffered: VecDeque::new(),
+            failures: 0,
+        }
+    }
+
+    pub(crate) fn advance(&mut self, page: Page) {
+        self.cursor = page.next_cursor.map(Some);
+        self.buffered = page.entries.into();
+        self.failures = 0;
+    }
+
+    /// Keeps the cursor for another attempt if `error` may be transient
+    pub(crate) fn fail(&mut self, error: &WireKVSError) {
+        self.failures += 1;
+        let transient = match error {
+            WireKVSError::Network(_) | WireKVSError::Timeout | WireKVSError::RateLimited { .. } => true,
+            WireKVSError::Api { status, .. } => *status >= 500,
+            _ => false,
+        };
+        if !transient || self.failures >= WALK_MAX_FAILURES {
+            self.cursor = None;
+        }
+    }
+}
+
+/// Flattens an entries body into key/value pairs
+///
+/// Accepts either an object mapping keys to values or an array of
+/// `{ "key": ..., "value": ... }` objects.
+pub(crate) fn entries_from_value(body: Value) -> Result<Vec<(String, Value)>, WireKVSError> {
+    match body {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        Value::Array(items) => items.into_iter().map(entry_from_item).collect(),
+        Value::Null => Ok(Vec::new()),
+        other => Err(unexpected(&other)),
+    }
+}
+
+fn entry_from_item(item: Value) -> Result<(String, Value), WireKVSError> {
+    let mut map: Map<String, Value> = match item {
+        Value::Object(map) => map,
+        other => return Err(unexpected(&other)),
+    };
+    match map.remove("key") {
+        Some(Value::String(key)) => Ok((key, map.remove("value").unwrap_or(Value::Null))),
+        _ => Err(unexpected(&Value::Object(map))),
+    }
+}
+
+fn unexpected(value: &Value) -> WireKVSError {
+    decode_error(serde_json::Error::custom("unexpected entries format"), value.to_string().as_bytes())
+}
@@ -0,0 +1,98 @@
+//! Proxy selection and HTTP `CONNECT` tunnelling for the event socket
+//!
+//! reqwest handles proxies for HTTP requests on its own. tokio-tungstenite has
+//! no proxy support, so the event socket opens a `CONNECT` tunnel through the
+//! proxy itself and runs the WebSocket handshake over it.
+
+use std::io;
+
+use base64::Engine as _;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::config::WireKVSConfig;
+
+/// Largest proxy response header accepted before giving up on the tunnel
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+/// Picks the proxy for a socket URL, or `None` to connect directly
+///
+/// Proxies set in the config take precedence. If none are set, the standard
+/// `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` environment
+/// variables are used, both in upper and lower case, matching reqwest.
+pub(crate) fn for_socket(config: &WireKVSConfig, url: &Url) -> Option<Url> {
+    let secure = url.scheme() == "wss";
+    let explicit = config.http_proxy.is_some() || config.https_proxy.is_some();
+    let (proxy, no_proxy) = if explicit {
+        let proxy = if secure { &config.https_proxy } else { &config.http_proxy };
+        (proxy.clone(), config.no_proxy.clone())
+    } else {
+        let proxy = if secure { env("HTTPS_PROXY") } else { env("HTTP_PROXY") };
+        (proxy.or_else(|| env("ALL_PROXY")), env("NO_PROXY"))
+    };
+
+    let host = url.host_str()?;
+    if no_proxy.is_some_and(|no_proxy| bypasses(&no_proxy, host)) {
+        return None;
+    }
+    proxy.and_then(|proxy| Url::parse(&proxy).ok())
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .or_else(|_| std::env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Whether `host` matches a comma-separated `NO_PROXY` list
+///
+/// Entries match the host itself and its subdomains, and `*` matches every host.
+fn bypasses(no_proxy: &str, host: &str) -> bool {
+    no_proxy.split(',').map(str::trim).any(|entry| {
+        let entry = entry.trim_start_matches('.');
+        entry == "*" || (!entry.is_empty() && (host == entry || host.ends_with(&format!(".{}", entry))))
+    })
+}
+
+/// Opens a TCP connection to `target` through an HTTP proxy
+///
+/// Credentials in the proxy URL are sent as basic `Proxy-Authorization`.
+pub(crate) async fn tunnel(proxy: &Url, target: &Url) -> io::Result<TcpStream> {
+    if proxy.scheme() != "http" {
+        return Err(io::Error::other(format!("unsupported proxy scheme {:?} for the event socket", proxy.scheme())));
+    }
+    let proxy_host = proxy.host_str().ok_or_else(|| io::Error::other("proxy url has no host"))?;
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+    let target_host = target.host_str().ok_or_else(|| io::Error::other("socket url has no host"))?;
+    let target_port = target.port_or_known_default().unwrap_or(443);
+    let authority = format!("{}:{}", target_host, target_port);
+
+    let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if !proxy.username().is_empty() {
+        let username = urlencoding::decode(proxy.username()).map_err(io::Error::other)?;
+        let password = urlencoding::decode(proxy.password().unwrap_or("")).map_err(io::Error::other)?;
+        let credentials = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing past the response head is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            return Err(io::Error::other("proxy response head too large"));
+        }
+        let byte = stream.read_u8().await?;
+        head.push(byte);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(io::Error::other(format!("proxy refused tunnel: {}", status_line))),
+    }
+}
@@ -0,0 +1,80 @@
+use serde_json::{Map, Value};
+
+use crate::timestamp;
+
+/// Metadata the server returns for a write, see `WireKVSDatabase::set_returning`
+///
+/// The public API doesn't document a write response, so every field is
+/// optional. `version` is read from a `version` field and `updated_at` from
+/// `updatedAt`; both are `None` if the server responds with an empty or
+/// differently shaped body.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetResponse {
+    /// Revision of the key after the write
+    pub version: Option<u64>,
+    /// When the key was written, as sent by the server, see `updated_at_utc`
+    pub updated_at: Option<String>,
+}
+
+/// A value read together with the metadata the server wrapped it in, see
+/// `WireKVSDatabase::get_with_metadata`
+///
+/// The hosted API returns values bare, so `version` and `updated_at` are
+/// only set by servers that wrap them as
+/// `{ "value": ..., "version": 3, "updatedAt": "..." }`. A body counts as such
+/// an envelope if it is an object with a `value` field and no fields besides
+/// `value`, `version` and `updatedAt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueWithMeta {
+    /// The stored value, without the envelope
+    pub value: Value,
+    /// Revision of the key, as used by `SetResponse::version`
+    pub version: Option<u64>,
+    /// When the key was last written, as sent by the server, see `updated_at_utc`
+    pub updated_at: Option<String>,
+}
+
+impl ValueWithMeta {
+    pub(crate) fn from_body(body: Value) -> ValueWithMeta {
+        match body {
+            Value::Object(mut map) if is_envelope(&map) => ValueWithMeta {
+                version: map.get("version").and_then(Value::as_u64),
+                updated_at: map.get("updatedAt").and_then(timestamp::text),
+                value: map.remove("value").unwrap_or_default(),
+            },
+            value => ValueWithMeta {
+                value,
+                version: None,
+                updated_at: None,
+            },
+        }
+    }
+
+    /// `updated_at` parsed from RFC 3339 or epoch milliseconds, `None` if missing or unparseable
+    #[cfg(feature = "chrono")]
+    pub fn updated_at_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.updated_at.as_deref().and_then(crate::timestamp::parse)
+    }
+}
+
+fn is_envelope(map: &Map<String, Value>) -> bool {
+    map.contains_key("value") && map.keys().all(|key| matches!(key.as_str(), "value" | "version" | "updatedAt"))
+}
+
+impl SetResponse {
+    pub(crate) fn from_body(body: &[u8]) -> SetResponse {
+        let Ok(Value::Object(map)) = serde_json::from_slice::<Value>(body) else {
+            return SetResponse::default();
+        };
+        SetResponse {
+            version: map.get("version").and_then(Value::as_u64),
+            updated_at: map.get("updatedAt").and_then(timestamp::text),
+        }
+    }
+
+    /// `updated_at` parsed from RFC 3339 or epoch milliseconds, `None` if missing or unparseable
+    #[cfg(feature = "chrono")]
+    pub fn updated_at_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.updated_at.as_deref().and_then(crate::timestamp::parse)
+    }
+}
@@ -0,0 +1,70 @@
+//! Full-state resync after the event socket reconnects, see
+//! `WireKVSConfig::resync_on_reconnect`
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{page, ConnectionState, WireKVSDatabase};
+
+/// Spawns the task that resyncs `db` every time `state` comes back to `Connected`
+///
+/// `db` must not hold the event socket, or the task would keep it open. The
+/// task ends once the socket is gone and `state` closes, or when `cancel` fires.
+pub(crate) fn spawn(
+    db: WireKVSDatabase,
+    mut state: watch::Receiver<ConnectionState>,
+    max_jitter: Duration,
+    cancel: CancellationToken,
+) {
+    let resync_after_reconnects = async move {
+        let mut was_connected = true;
+        while state.changed().await.is_ok() {
+            let connected = *state.borrow_and_update() == ConnectionState::Connected;
+            if connected && !was_connected {
+                resync(&db, max_jitter).await;
+            }
+            was_connected = connected;
+        }
+    };
+    tokio::spawn(async move { cancel.run_until_cancelled(resync_after_reconnects).await });
+}
+
+/// Re-reads every entry after a random delay and emits it as a `set` event
+///
+/// Keys that changed while the entries were being fetched are skipped, so
+/// their newer event isn't followed by the older snapshot value.
+async fn resync(db: &WireKVSDatabase, max_jitter: Duration) {
+    // Spreads out the resyncs of clients that all reconnect after a server restart
+    tokio::time::sleep(max_jitter.mul_f64(rand::random::<f64>())).await;
+    let mut newer = db.events.subscribe_internal();
+    let entries = match db.get_all_entries().await.and_then(page::entries_from_value) {
+        Ok(entries) => entries,
+        Err(_e) => {
+            debug!(error = %_e, "resync after reconnect failed");
+            return;
+        }
+    };
+    let mut changed = HashSet::new();
+    loop {
+        match newer.try_recv() {
+            Ok(envelope) => {
+                if let Some(key) = envelope.event.key() {
+                    changed.insert(key.to_string());
+                }
+            }
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => break,
+        }
+    }
+    debug!(entries = entries.len(), "resyncing after reconnect");
+    for (key, value) in entries {
+        if !changed.contains(&key) {
+            db.events.send(json!({ "type": "set", "key": key, "value": value }));
+        }
+    }
+}
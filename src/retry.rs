@@ -0,0 +1,80 @@
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::config::WireKVSConfig;
+use crate::error::{retry_after, WireKVSError};
+
+/// Sends `request`, retrying transient failures according to `config.retry`
+///
+/// Every attempt first waits for a `config.concurrency_limit` permit, then for
+/// `config.rate_limit`, if set. The permit is released once the response
+/// headers arrive, before any backoff. A 429 response
+/// is retried after its `Retry-After` delay, as long as that fits within
+/// `RetryPolicy::max_delay`. The response is returned as-is once it is no
+/// longer retryable, so status handling stays with the caller.
+pub(crate) async fn send(
+    config: &WireKVSConfig,
+    idempotent: bool,
+    request: RequestBuilder,
+) -> Result<Response, WireKVSError> {
+    let policy = &config.retry;
+    let max_attempts = if idempotent || policy.retry_non_idempotent {
+        policy.max_attempts.max(1)
+    } else {
+        1
+    };
+
+    let mut attempt = 0;
+    loop {
+        let permit = match &config.concurrency_limit {
+            Some(limit) => Some(limit.acquire().await),
+            None => None,
+        };
+        if let Some(limiter) = &config.rate_limit {
+            limiter.acquire().await?;
+        }
+        let Some(current) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        let result = current.send().await;
+        drop(permit);
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(response) => tracing::debug!(
+                url = %response.url(),
+                status = response.status().as_u16(),
+                attempt,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "http response"
+            ),
+            Err(e) => tracing::debug!(error = %e, attempt, "http request failed"),
+        }
+
+        let last = attempt + 1 >= max_attempts;
+        let delay = match result {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                // Honor the server's hint unless it asks for a longer pause than the policy allows
+                match retry_after(response.headers()) {
+                    Some(wait) if !last && wait <= policy.max_delay => wait,
+                    None if !last => policy.backoff(attempt),
+                    _ => return Ok(response),
+                }
+            }
+            Ok(response) if last || !is_retryable_status(response.status()) => return Ok(response),
+            Err(e) if last || e.is_builder() => return Err(e.into()),
+            _ => policy.backoff(attempt),
+        };
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
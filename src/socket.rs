@@ -0,0 +1,388 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::{HeaderValue, AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL, USER_AGENT};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{
+    client_async_tls_with_config, connect_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream,
+};
+use url::Url;
+
+use crate::auth::Token;
+use crate::config::{ReconnectPolicy, SocketAuth, WireKVSConfig};
+use crate::error::WireKVSError;
+use crate::event::Broadcaster;
+use crate::{metrics, proxy, tls};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Write half of the current connection, `None` while there is none
+///
+/// The socket is split so reading and writing never wait on each other. The
+/// read half is owned by the background task alone, the only reader, and
+/// needs no lock. The write half is shared by the task, which sends pings and
+/// the close frame, and every `Outbox`. It sits behind an async mutex that is
+/// held for the whole write of a frame, so concurrent writers never
+/// interleave partial frames, and the task swaps in the new half after a
+/// reconnect under the same lock.
+type Writer = Arc<Mutex<Option<SplitSink<WsStream, Message>>>>;
+
+/// Lifecycle of the event socket, observable through `WireKVSDatabase::connection_state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The initial connection is being established
+    Connecting,
+    /// The socket is live and events are flowing
+    Connected,
+    /// The socket dropped and is waiting before the next reconnect attempt
+    Reconnecting,
+    /// The socket is closed and won't reconnect on its own
+    Disconnected,
+}
+
+/// Snapshot of the event socket's health, see `WireKVSDatabase::connection_diagnostics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionDiagnostics {
+    /// Whether the socket is live right now
+    pub connected: bool,
+    /// Lifecycle state the socket is in
+    pub state: ConnectionState,
+    /// When the last event frame arrived, `None` if none has yet
+    pub last_event_at: Option<std::time::Instant>,
+    /// Number of times the socket reconnected after dropping
+    pub reconnect_count: u64,
+    /// Number of event frames received from the server, across reconnects
+    pub events_received: u64,
+    /// Number of receivers currently subscribed to events, including the
+    /// crate's own background consumers such as the cache
+    pub subscriber_count: usize,
+}
+
+/// Counters the background task updates as frames arrive
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    events_received: AtomicU64,
+    reconnects: AtomicU64,
+    last_event_at: std::sync::Mutex<Option<std::time::Instant>>,
+}
+
+impl Stats {
+    pub(crate) fn diagnose(&self, state: ConnectionState, subscriber_count: usize) -> ConnectionDiagnostics {
+        ConnectionDiagnostics {
+            connected: state == ConnectionState::Connected,
+            state,
+            last_event_at: *self.last_event_at.lock().unwrap(),
+            reconnect_count: self.reconnects.load(Ordering::Relaxed),
+            events_received: self.events_received.load(Ordering::Relaxed),
+            subscriber_count,
+        }
+    }
+
+    fn event_received(&self) {
+        self.events_received.fetch_add(1, Ordering::Relaxed);
+        *self.last_event_at.lock().unwrap() = Some(std::time::Instant::now());
+    }
+}
+
+/// Handle to the background task that reads the event socket
+pub(crate) struct EventSocket {
+    shutdown: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    state: Arc<watch::Sender<ConnectionState>>,
+    stats: Arc<Stats>,
+    writer: Writer,
+}
+
+/// Writes frames straight to the socket's write half, see `Writer`
+#[derive(Clone)]
+pub(crate) struct Outbox {
+    writer: Writer,
+}
+
+impl Outbox {
+    /// Writes `message` as a JSON text frame and waits until it was flushed to the socket
+    pub(crate) async fn send(&self, message: &Value) -> Result<(), WireKVSError> {
+        write(&self.writer, Message::text(message.to_string())).await
+    }
+}
+
+/// Settings the background task needs for the lifetime of the socket
+struct Options {
+    url: Url,
+    proxy: Option<Url>,
+    user_agent: String,
+    // Read again for every handshake, so reconnects use a refreshed key
+    access_key: Arc<Token>,
+    auth: SocketAuth,
+    subprotocols: Vec<String>,
+    tls: Option<Connector>,
+    reconnect: ReconnectPolicy,
+    ping_interval: Duration,
+    pong_timeout: Duration,
+}
+
+impl EventSocket {
+    /// Connects to `url` and spawns the task that forwards events into `events`
+    ///
+    /// The current `access_key` is sent with every handshake, including
+    /// reconnects, as a header with `SocketAuth::Header` or in place of the
+    /// key `url` carries otherwise. The task stops once `cancel` is
+    /// cancelled, or when the socket is closed or dropped.
+    pub(crate) async fn connect(
+        url: Url,
+        access_key: Arc<Token>,
+        config: &WireKVSConfig,
+        events: Broadcaster,
+        cancel: &CancellationToken,
+    ) -> Result<Self, WireKVSError> {
+        let options = Options {
+            proxy: proxy::for_socket(config, &url),
+            url,
+            user_agent: config.user_agent().to_string(),
+            access_key,
+            auth: config.ws_auth,
+            subprotocols: config.ws_subprotocols.clone(),
+            tls: tls::connector(config)?,
+            reconnect: config.reconnect.clone(),
+            ping_interval: config.ping_interval,
+            pong_timeout: config.pong_timeout,
+        };
+        let (sink, source) = open(&options).await?.split();
+        let writer = Arc::new(Mutex::new(Some(sink)));
+        let state = Arc::new(watch::Sender::new(ConnectionState::Connected));
+        let stats = Arc::new(Stats::default());
+        let shutdown = cancel.child_token();
+        let task = tokio::spawn(run(
+            source,
+            writer.clone(),
+            options,
+            events,
+            state.clone(),
+            stats.clone(),
+            shutdown.clone(),
+        ));
+        Ok(EventSocket {
+            shutdown,
+            task: Some(task),
+            state,
+            stats,
+            writer,
+        })
+    }
+
+    pub(crate) fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    pub(crate) fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    pub(crate) fn outbox(&self) -> Outbox {
+        Outbox {
+            writer: self.writer.clone(),
+        }
+    }
+
+    /// Stops the task, sending a close frame if the socket is still open
+    pub(crate) async fn close(&mut self) {
+        self.shutdown.cancel();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+        self.state.send_replace(ConnectionState::Disconnected);
+    }
+}
+
+impl Drop for EventSocket {
+    // Async drop isn't available, so the close handshake can't be awaited
+    // here. Signalling the task is enough: it sends the close frame on its own
+    // as long as the runtime keeps running.
+    fn drop(&mut self) {
+        if !self.shutdown.is_cancelled() {
+            self.shutdown.cancel();
+            self.state.send_replace(ConnectionState::Disconnected);
+        }
+    }
+}
+
+async fn open(options: &Options) -> Result<WsStream, WireKVSError> {
+    let access_key = options.access_key.current();
+    let mut request = match options.auth {
+        SocketAuth::QueryParam => with_access_key(&options.url, &access_key).as_str().into_client_request()?,
+        SocketAuth::Header => options.url.as_str().into_client_request()?,
+    };
+    let user_agent = HeaderValue::from_str(&options.user_agent).map_err(tungstenite::Error::from)?;
+    request.headers_mut().insert(USER_AGENT, user_agent);
+    if options.auth == SocketAuth::Header {
+        let authorization = HeaderValue::from_str(&access_key).map_err(tungstenite::Error::from)?;
+        request.headers_mut().insert(AUTHORIZATION, authorization);
+    }
+    if !options.subprotocols.is_empty() {
+        let protocols = HeaderValue::from_str(&options.subprotocols.join(", ")).map_err(tungstenite::Error::from)?;
+        request.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, protocols);
+    }
+    let (ws, _) = match &options.proxy {
+        Some(proxy) => {
+            let stream = proxy::tunnel(proxy, &options.url).await.map_err(tungstenite::Error::Io)?;
+            client_async_tls_with_config(request, stream, None, options.tls.clone()).await?
+        }
+        None => connect_async_tls_with_config(request, None, false, options.tls.clone()).await?,
+    };
+    Ok(ws)
+}
+
+/// Writes one frame, failing with `NotConnected` while there is no connection
+///
+/// A failed write drops the write half, so later frames fail fast until the
+/// task reconnects.
+async fn write(writer: &Writer, message: Message) -> Result<(), WireKVSError> {
+    let mut writer = writer.lock().await;
+    let sink = writer.as_mut().ok_or(WireKVSError::NotConnected)?;
+    if let Err(e) = sink.send(message).await {
+        *writer = None;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+async fn run(
+    mut source: SplitStream<WsStream>,
+    writer: Writer,
+    options: Options,
+    events: Broadcaster,
+    state: Arc<watch::Sender<ConnectionState>>,
+    stats: Arc<Stats>,
+    shutdown: CancellationToken,
+) {
+    let keepalive = !options.ping_interval.is_zero();
+    loop {
+        // A zero interval disables keepalive; the timer still needs a valid period
+        let period = if keepalive { options.ping_interval } else { Duration::from_secs(3600) };
+        let mut ping = tokio::time::interval_at(Instant::now() + period, period);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut pong_deadline: Option<Instant> = None;
+
+        loop {
+            let deadline = pong_deadline.unwrap_or_else(|| Instant::now() + period);
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    if let Some(mut sink) = writer.lock().await.take() {
+                        let _ = sink.close().await;
+                    }
+                    state.send_replace(ConnectionState::Disconnected);
+                    return;
+                }
+                _ = ping.tick(), if keepalive && pong_deadline.is_none() => {
+                    if write(&writer, Message::Ping(Default::default())).await.is_err() {
+                        break;
+                    }
+                    pong_deadline = Some(Instant::now() + options.pong_timeout);
+                }
+                _ = tokio::time::sleep_until(deadline), if pong_deadline.is_some() => {
+                    debug!("no pong received in time, treating event socket as dead");
+                    break;
+                }
+                message = source.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(Message::Pong(_))) => pong_deadline = None,
+                    Some(Ok(message)) => {
+                        if let Some(event) = parse_event(message) {
+                            debug!(event_type = ?event.get("type"), "event received");
+                            metrics::event_delivered();
+                            stats.event_received();
+                            events.send(event);
+                        }
+                    }
+                },
+            }
+        }
+
+        // Frames sent from now on fail instead of waiting for the reconnect
+        writer.lock().await.take();
+        state.send_replace(ConnectionState::Reconnecting);
+        events.send(json!({ "type": "disconnected" }));
+        debug!("event socket dropped");
+
+        match reconnect(&options, &shutdown).await {
+            Some(new_ws) => {
+                debug!("event socket reconnected");
+                metrics::reconnected();
+                stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                let (sink, new_source) = new_ws.split();
+                source = new_source;
+                *writer.lock().await = Some(sink);
+                state.send_replace(ConnectionState::Connected);
+                events.send(json!({ "type": "connected" }));
+            }
+            None => {
+                debug!("gave up reconnecting event socket");
+                state.send_replace(ConnectionState::Disconnected);
+                return;
+            }
+        }
+    }
+}
+
+/// Decodes a data frame into an event
+///
+/// Text and binary frames carrying JSON are forwarded. Anything else, such as
+/// non-JSON payloads or ping/pong control frames, is skipped. Pongs for
+/// incoming pings are sent automatically by tungstenite.
+fn parse_event(message: Message) -> Option<Value> {
+    match message {
+        Message::Text(text) => serde_json::from_str(&text).ok(),
+        Message::Binary(data) => serde_json::from_slice(&data).ok(),
+        _ => None,
+    }
+}
+
+/// Retries the connection with exponential backoff, giving up after
+/// `policy.max_retries` attempts or when shutdown is requested
+async fn reconnect(options: &Options, shutdown: &CancellationToken) -> Option<WsStream> {
+    let policy = &options.reconnect;
+    for attempt in 0..policy.max_retries {
+        debug!(attempt, "reconnecting event socket");
+        tokio::select! {
+            _ = shutdown.cancelled() => return None,
+            _ = tokio::time::sleep(policy.backoff(attempt)) => {}
+        }
+        let opened = tokio::select! {
+            _ = shutdown.cancelled() => return None,
+            opened = open(options) => opened,
+        };
+        if let Ok(ws) = opened {
+            return Some(ws);
+        }
+    }
+    None
+}
+
+/// Returns the socket URL with the access key replaced, safe to log
+pub(crate) fn redact(url: &Url) -> Url {
+    with_access_key(url, "redacted")
+}
+
+/// Returns `url` with the value of its `accessKey` parameter replaced by `access_key`
+fn with_access_key(url: &Url, access_key: &str) -> Url {
+    let mut replaced = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if name == "accessKey" { access_key.into() } else { value };
+            (name.into_owned(), value.into_owned())
+        })
+        .collect();
+    replaced.query_pairs_mut().clear().extend_pairs(pairs);
+    replaced
+}
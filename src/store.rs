@@ -0,0 +1,86 @@
+use futures::future::BoxFuture;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{WireKVSDatabase, WireKVSError};
+
+/// Core database operations, shared by every backend
+///
+/// Write application code against this trait to swap `WireKVSDatabase` for a
+/// fake in tests, such as `InMemoryDatabase` with the `in-memory` feature.
+/// The methods return boxed futures so the trait can be used as
+/// `dyn KeyValueStore`. Each backend keeps its inherent methods, which take
+/// precedence in method calls, so existing callers are unaffected.
+///
+/// # Example
+/// ```no_run
+/// use serde_json::json;
+/// use wirekvs::{KeyValueStore, WireKVSError};
+///
+/// async fn greet(store: &impl KeyValueStore) -> Result<(), WireKVSError> {
+///     store.set("greeting", json!("Hello!")).await?;
+///     println!("{}", store.get("greeting").await?);
+///     Ok(())
+/// }
+/// ```
+pub trait KeyValueStore: Send + Sync {
+    /// Gets a value by key, failing with `WireKVSError::NotFound` if it doesn't exist
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Value, WireKVSError>>;
+
+    /// Sets a value for a specific key
+    fn set<'a>(&'a self, key: &'a str, value: Value) -> BoxFuture<'a, Result<(), WireKVSError>>;
+
+    /// Deletes a value by key
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), WireKVSError>>;
+
+    /// Gets all entries of the database
+    fn get_all_entries(&self) -> BoxFuture<'_, Result<Value, WireKVSError>>;
+
+    /// Subscribe to raw JSON change events
+    fn subscribe(&self) -> broadcast::Receiver<Value>;
+}
+
+impl KeyValueStore for WireKVSDatabase {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Value, WireKVSError>> {
+        Box::pin(WireKVSDatabase::get(self, key))
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Value) -> BoxFuture<'a, Result<(), WireKVSError>> {
+        Box::pin(WireKVSDatabase::set(self, key, value))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), WireKVSError>> {
+        Box::pin(WireKVSDatabase::delete(self, key))
+    }
+
+    fn get_all_entries(&self) -> BoxFuture<'_, Result<Value, WireKVSError>> {
+        Box::pin(WireKVSDatabase::get_all_entries(self))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Value> {
+        WireKVSDatabase::subscribe(self)
+    }
+}
+
+#[cfg(feature = "in-memory")]
+impl KeyValueStore for crate::InMemoryDatabase {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Value, WireKVSError>> {
+        Box::pin(crate::InMemoryDatabase::get(self, key))
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Value) -> BoxFuture<'a, Result<(), WireKVSError>> {
+        Box::pin(crate::InMemoryDatabase::set(self, key, value))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), WireKVSError>> {
+        Box::pin(crate::InMemoryDatabase::delete(self, key))
+    }
+
+    fn get_all_entries(&self) -> BoxFuture<'_, Result<Value, WireKVSError>> {
+        Box::pin(crate::InMemoryDatabase::get_all_entries(self))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Value> {
+        crate::InMemoryDatabase::subscribe(self)
+    }
+}
@@ -0,0 +1,83 @@
+//! Reading and writing values as byte streams, for values too large to buffer
+
+use std::io;
+
+use futures::stream::{self, StreamExt};
+use http_body::Frame;
+use http_body_util::StreamBody;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Body;
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::error::check_status;
+use crate::{metrics, WireKVSDatabase, WireKVSError};
+
+impl WireKVSDatabase {
+    /// Gets a value as a stream of its JSON encoding, without buffering it
+    ///
+    /// The returned reader yields the response body as it arrives, so a huge
+    /// value can be written to a file or parsed incrementally while only one
+    /// chunk is held in memory. The bytes are the value's JSON text, e.g. a
+    /// base64 string still has its quotes. Status errors such as
+    /// `WireKVSError::NotFound` are reported before any byte is read; a
+    /// connection that fails midway surfaces as an `io::Error` from the
+    /// reader. The cache and `WireKVSConfig::max_response_size` don't apply,
+    /// but `WireKVSConfig::request_timeout` still bounds the whole download.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let mut value = db.get_stream("backup").await.unwrap();
+    /// let mut file = tokio::fs::File::create("backup.json").await.unwrap();
+    /// tokio::io::copy(&mut value, &mut file).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn get_stream(&self, key: &str) -> Result<impl AsyncRead + Send + Unpin, WireKVSError> {
+        let request = self.client.get(self.key_url(key)?);
+        let response = metrics::observe("get_stream", async {
+            let response = self.send(true, request).await?;
+            check_status(response).await
+        })
+        .await?;
+        let chunks = stream::try_unfold(response, |mut response| async move {
+            let chunk = response.chunk().await.map_err(io::Error::other)?;
+            Ok::<_, io::Error>(chunk.map(|chunk| (chunk, response)))
+        });
+        Ok(StreamReader::new(Box::pin(chunks)))
+    }
+
+    /// Sets a value from a reader producing its JSON encoding, without buffering it
+    ///
+    /// The reader's bytes are uploaded as they are read and must form exactly
+    /// one JSON document, such as a quoted base64 string; the server rejects
+    /// anything else. Since the body can't be replayed, the upload is never
+    /// retried, and `WireKVSConfig::request_timeout` must leave enough time
+    /// to send all of it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// let file = tokio::fs::File::open("backup.json").await.unwrap();
+    /// db.set_stream("backup", file).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn set_stream<R>(&self, key: &str, reader: R) -> Result<(), WireKVSError>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let frames = ReaderStream::new(reader).map(|chunk| chunk.map(Frame::data));
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::wrap(StreamBody::new(frames)));
+        metrics::observe("set_stream", async {
+            let response = self.send(false, request).await?;
+            check_status(response).await
+        })
+        .await?;
+        self.invalidate_cached(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{page, Event, EventEnvelope, WireKVSDatabase};
+
+/// Event subscription that survives falling behind the socket
+///
+/// A plain `broadcast::Receiver` reports `RecvError::Lagged` when its buffer
+/// overflows, and the common `while let Ok(event) = rx.recv().await` loop
+/// stops for good at that point. `Subscription::recv` instead skips the
+/// missed events, adds them to `skipped`, and keeps receiving.
+///
+/// After `resync_from`, the current contents of the database are re-read
+/// after every lag and replayed as `Event::Set` events before newer events,
+/// so state built from the stream converges again. Keys deleted during the
+/// lag are not replayed as `Event::Deleted`.
+///
+/// # Example
+/// ```no_run
+/// # async fn example(db: wirekvs::WireKVSDatabase) {
+/// let mut events = db.subscription().resync_from(&db);
+/// while let Some(event) = events.recv().await {
+///     println!("Event: {:?}", event);
+/// }
+/// # }
+/// ```
+pub struct Subscription {
+    rx: broadcast::Receiver<EventEnvelope>,
+    resync: Option<WireKVSDatabase>,
+    replay: VecDeque<Event>,
+    skipped: u64,
+}
+
+impl Subscription {
+    pub(crate) fn new(rx: broadcast::Receiver<EventEnvelope>) -> Self {
+        Subscription {
+            rx,
+            resync: None,
+            replay: VecDeque::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Re-reads all entries from `db` after a lag and replays them as `Event::Set`
+    ///
+    /// The subscription keeps a clone of `db`, and with it the event socket,
+    /// alive until it is dropped.
+    pub fn resync_from(mut self, db: &WireKVSDatabase) -> Self {
+        self.resync = Some(db.clone());
+        self
+    }
+
+    /// Receives the next event, or `None` once every database handle is gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.replay.pop_front() {
+                return Some(event);
+            }
+            match self.rx.recv().await {
+                Ok(envelope) => return Some(envelope.event),
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!(skipped, "subscriber lagged behind the event socket");
+                    self.skipped += skipped;
+                    self.resync().await;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Total number of events skipped because this subscriber fell behind
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    async fn resync(&mut self) {
+        let Some(db) = &self.resync else {
+            return;
+        };
+        match db.get_all_entries().await.and_then(page::entries_from_value) {
+            Ok(entries) => {
+                let events = entries.into_iter().map(|(key, value)| Event::Set { key, value });
+                self.replay.extend(events);
+            }
+            Err(_e) => {
+                debug!(error = %_e, "resync after lag failed");
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Server timestamps, which arrive as RFC 3339 strings or as epoch milliseconds
+//!
+//! They are kept as text on every struct, with numbers written out in
+//! decimal, so nothing is lost when the `chrono` feature is off. With it, the
+//! `*_utc` accessors parse that text into a `DateTime<Utc>`.
+
+use serde_json::Value;
+
+/// Reads a timestamp field as text, accepting a string or an integer
+pub(crate) fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(millis) if millis.is_u64() || millis.is_i64() => Some(millis.to_string()),
+        _ => None,
+    }
+}
+
+/// `text` for serde derives, mapping anything else to `None`
+#[cfg(feature = "history")]
+pub(crate) fn deserialize_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(text(&serde::Deserialize::deserialize(deserializer)?))
+}
+
+/// Parses timestamp text as epoch milliseconds if it is an integer, and as
+/// RFC 3339 otherwise
+#[cfg(feature = "chrono")]
+pub(crate) fn parse(text: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    match text.parse::<i64>() {
+        Ok(millis) => chrono::DateTime::from_timestamp_millis(millis),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|time| time.with_timezone(&chrono::Utc)),
+    }
+}
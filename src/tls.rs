@@ -0,0 +1,82 @@
+use tokio_tungstenite::Connector;
+
+use crate::config::WireKVSConfig;
+use crate::error::WireKVSError;
+
+/// A root certificate to trust in addition to the system's, see
+/// `WireKVSBuilder::add_root_certificate`
+///
+/// # Example
+/// ```no_run
+/// use wirekvs::Certificate;
+///
+/// let pem = std::fs::read("internal-ca.pem").unwrap();
+/// let ca = Certificate::from_pem(&pem).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    encoded: Encoded,
+}
+
+#[derive(Debug, Clone)]
+enum Encoded {
+    Pem(Vec<u8>),
+    Der(Vec<u8>),
+}
+
+impl Certificate {
+    /// Parses a PEM encoded certificate
+    ///
+    /// Fails with `WireKVSError::InvalidConfig` if `pem` isn't a certificate.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, WireKVSError> {
+        let certificate = Certificate {
+            encoded: Encoded::Pem(pem.to_vec()),
+        };
+        certificate.for_socket()?;
+        Ok(certificate)
+    }
+
+    /// Parses a DER encoded certificate
+    ///
+    /// Fails with `WireKVSError::InvalidConfig` if `der` isn't a certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self, WireKVSError> {
+        let certificate = Certificate {
+            encoded: Encoded::Der(der.to_vec()),
+        };
+        certificate.for_socket()?;
+        Ok(certificate)
+    }
+
+    pub(crate) fn for_http(&self) -> Result<reqwest::Certificate, WireKVSError> {
+        match &self.encoded {
+            Encoded::Pem(pem) => reqwest::Certificate::from_pem(pem),
+            Encoded::Der(der) => reqwest::Certificate::from_der(der),
+        }
+        .map_err(|e| WireKVSError::InvalidConfig(format!("invalid certificate: {}", e)))
+    }
+
+    fn for_socket(&self) -> Result<native_tls::Certificate, WireKVSError> {
+        match &self.encoded {
+            Encoded::Pem(pem) => native_tls::Certificate::from_pem(pem),
+            Encoded::Der(der) => native_tls::Certificate::from_der(der),
+        }
+        .map_err(|e| WireKVSError::InvalidConfig(format!("invalid certificate: {}", e)))
+    }
+}
+
+/// Builds the TLS connector for `wss://` event sockets, or `None` when the
+/// config doesn't change the default trust settings
+pub(crate) fn connector(config: &WireKVSConfig) -> Result<Option<Connector>, WireKVSError> {
+    if config.root_certificates.is_empty() && !config.danger_accept_invalid_certs {
+        return Ok(None);
+    }
+    let mut builder = native_tls::TlsConnector::builder();
+    for certificate in &config.root_certificates {
+        builder.add_root_certificate(certificate.for_socket()?);
+    }
+    builder.danger_accept_invalid_certs(config.danger_accept_invalid_certs);
+    let connector = builder
+        .build()
+        .map_err(|e| WireKVSError::InvalidConfig(format!("failed to set up TLS: {}", e)))?;
+    Ok(Some(Connector::NativeTls(connector)))
+}
@@ -0,0 +1,67 @@
+//! Key expiry, enabled by the `ttl` feature
+//!
+//! The public WireKVS API doesn't document key expiry, so these methods only
+//! work against deployments that support it. They assume the server accepts a
+//! `ttl` query parameter (in whole seconds) when setting a key, and exposes the
+//! remaining lifetime at `/database/{id}/{key}/ttl` as `{ "ttl": seconds }`
+//! with `null` for keys that never expire.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::error::{check_status, parse_json};
+use crate::{metrics, WireKVSDatabase, WireKVSError};
+
+impl WireKVSDatabase {
+    /// Sets a value that expires after `ttl`
+    ///
+    /// The server tracks expiry in whole seconds, so `ttl` is rounded up to
+    /// the next second.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// db.set_with_ttl("session", json!("token"), Duration::from_secs(60)).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn set_with_ttl(&self, key: &str, value: Value, ttl: Duration) -> Result<(), WireKVSError> {
+        let seconds = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+        let request = self
+            .client
+            .post(self.key_url(key)?)
+            .query(&[("ttl", seconds.max(1))])
+            .json(&value);
+        metrics::observe("set_with_ttl", async {
+            let response = self.send(false, request).await?;
+            check_status(response).await
+        })
+        .await?;
+        self.invalidate_cached(key);
+        Ok(())
+    }
+
+    /// Gets the remaining lifetime of a key
+    ///
+    /// Returns `None` for keys without an expiry.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example(db: wirekvs::WireKVSDatabase) {
+    /// if let Some(remaining) = db.ttl("session").await.unwrap() {
+    ///     println!("Expires in {:?}", remaining);
+    /// }
+    /// # }
+    /// ```
+    pub async fn ttl(&self, key: &str) -> Result<Option<Duration>, WireKVSError> {
+        let request = self.client.get(self.key_resource_url(key, &["ttl"])?);
+        let body = metrics::observe("ttl", async {
+            let response = self.send(true, request).await?;
+            parse_json(response, self.config.max_response_size).await
+        })
+        .await?;
+        Ok(body.get("ttl").and_then(Value::as_u64).map(Duration::from_secs))
+    }
+}
@@ -0,0 +1,1837 @@
+//! Checks the requests the client sends against a mock HTTP server
+//!
+//! HTTP endpoints are stubbed with wiremock. Database handles also open the
+//! event socket on creation, so a bare WebSocket listener stands in for it.
+
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use wiremock::matchers::{body_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wirekvs::{
+    Certificate, ConcurrencyLimit, DatabaseConfig, DatabaseConfigUpdate, Event, RateLimiter, ReconnectPolicy, RetryPolicy, SocketAuth, WireKVS,
+    WireKVSConfig, WireKVSDatabase, WireKVSError,
+};
+
+/// Accepts event socket connections and keeps them open until the client leaves
+async fn event_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await {
+                    while let Some(Ok(_)) = ws.next().await {}
+                }
+            });
+        }
+    });
+    format!("ws://{}/events", addr)
+}
+
+/// Sends `event` to every socket client as soon as it connects
+async fn event_server_sending(event: serde_json::Value) -> String {
+    event_server_sending_all(vec![event]).await
+}
+
+/// Sends `events` in order to every socket client as soon as it connects
+async fn event_server_sending_all(events: Vec<serde_json::Value>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let events = events.clone();
+            tokio::spawn(async move {
+                if let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await {
+                    for event in events {
+                        let _ = ws.send(Message::text(event.to_string())).await;
+                    }
+                    while let Some(Ok(_)) = ws.next().await {}
+                }
+            });
+        }
+    });
+    format!("ws://{}/events", addr)
+}
+
+/// Closes the first socket connection right after the handshake and keeps later ones open
+async fn event_server_dropping_first() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut first = true;
+        while let Ok((stream, _)) = listener.accept().await {
+            let drop_it = std::mem::take(&mut first);
+            tokio::spawn(async move {
+                if let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await {
+                    if drop_it {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        let _ = ws.close(None).await;
+                        return;
+                    }
+                    while let Some(Ok(_)) = ws.next().await {}
+                }
+            });
+        }
+    });
+    format!("ws://{}/events", addr)
+}
+
+fn config(server: &MockServer, ws_base_url: String) -> WireKVSConfig {
+    WireKVSConfig {
+        base_url: server.uri(),
+        ws_base_url,
+        retry: RetryPolicy::disabled(),
+        ..Default::default()
+    }
+}
+
+async fn database(server: &MockServer) -> WireKVSDatabase {
+    let config = config(server, event_server().await);
+    WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap()
+}
+
+fn client(server: &MockServer) -> WireKVS {
+    WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn get_sends_access_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("Authorization", "access-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+}
+
+#[tokio::test]
+async fn requests_carry_the_crate_user_agent() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("User-Agent", concat!("wirekvs-rust/", env!("CARGO_PKG_VERSION"))))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    db.get("greeting").await.unwrap();
+}
+
+#[tokio::test]
+async fn get_maps_404_to_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert!(matches!(db.get("missing").await, Err(WireKVSError::NotFound)));
+}
+
+#[tokio::test]
+async fn get_opt_tells_missing_from_null() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/empty"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(null)))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get_opt("missing").await.unwrap(), None);
+    assert_eq!(db.get_opt("empty").await.unwrap(), Some(json!(null)));
+}
+
+#[tokio::test]
+async fn get_raw_returns_the_unread_response() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/missing"))
+        .respond_with(ResponseTemplate::new(404).insert_header("x-request-id", "abc").set_body_string("gone"))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let response = db.get_raw("missing").await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.headers()["x-request-id"], "abc");
+    assert_eq!(response.text().await.unwrap(), "gone");
+}
+
+#[tokio::test]
+async fn typed_getters_report_the_stored_type() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/visits"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("42")))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get_string("visits").await.unwrap().as_deref(), Some("42"));
+    assert!(matches!(
+        db.get_i64("visits").await,
+        Err(WireKVSError::TypeMismatch { expected: "integer", found: "string" })
+    ));
+    assert_eq!(db.get_bool("missing").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn get_if_modified_returns_none_on_304() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/config"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get_if_modified("config", "\"v1\"").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn get_many_leaves_out_missing_keys() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/a"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(1)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/b"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let values = db.get_many(&["a", "b"]).await.unwrap();
+    assert_eq!(values.len(), 1);
+    assert_eq!(values["a"], json!(1));
+}
+
+#[tokio::test]
+async fn set_posts_json_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/count"))
+        .and(header("Authorization", "access-key"))
+        .and(body_json(json!({ "n": 42 })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    db.set("count", json!({ "n": 42 })).await.unwrap();
+}
+
+#[tokio::test]
+async fn set_returning_parses_write_metadata() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/count"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": 7, "updatedAt": "2025-01-01T00:00:00Z" })))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let written = db.set_returning("count", json!(1)).await.unwrap();
+    assert_eq!(written.version, Some(7));
+    assert_eq!(written.updated_at.as_deref(), Some("2025-01-01T00:00:00Z"));
+}
+
+#[tokio::test]
+async fn patch_merges_into_the_stored_object() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/profile"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "name": "Ferris", "age": 8, "tags": ["crab"] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/profile"))
+        .and(body_json(json!({ "name": "Ferris", "age": 9, "home": { "city": "Zurich" } })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let patched = db
+        .patch("profile", json!({ "age": 9, "tags": null, "home": { "city": "Zurich" } }))
+        .await
+        .unwrap();
+    assert_eq!(patched["age"], json!(9));
+}
+
+#[tokio::test]
+async fn rename_refuses_to_overwrite() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/draft"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("text")))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/published"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("older text")))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    match db.rename("draft", "published", false).await {
+        Err(WireKVSError::AlreadyExists { key }) => assert_eq!(key, "published"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn values_stream_in_and_out() {
+    let server = MockServer::start().await;
+    let blob = json!({ "data": "aGVsbG8gd29ybGQ=" });
+    Mock::given(method("GET"))
+        .and(path("/database/db/blob"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&blob))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/copy"))
+        .and(header("Content-Type", "application/json"))
+        .and(body_json(&blob))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let mut downloaded = Vec::new();
+    tokio::io::copy(&mut db.get_stream("blob").await.unwrap(), &mut downloaded).await.unwrap();
+    assert_eq!(serde_json::from_slice::<serde_json::Value>(&downloaded).unwrap(), blob);
+    db.set_stream("copy", std::io::Cursor::new(downloaded)).await.unwrap();
+}
+
+#[tokio::test]
+async fn delete_sends_delete() {
+    let server = MockServer::start().await;
+    Mock::given(method("DELETE"))
+        .and(path("/database/db/old"))
+        .and(header("Authorization", "access-key"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    db.delete("old").await.unwrap();
+}
+
+#[tokio::test]
+async fn clear_deletes_every_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "a": 1, "b": 2 })))
+        .mount(&server)
+        .await;
+    for key in ["a", "b"] {
+        Mock::given(method("DELETE"))
+            .and(path(format!("/database/db/{}", key)))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let db = database(&server).await;
+    db.clear().await.unwrap();
+}
+
+#[tokio::test]
+async fn keys_are_percent_encoded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/users%2F1%20a"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(1)))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get("users/1 a").await.unwrap(), json!(1));
+}
+
+#[tokio::test]
+async fn reserved_and_non_ascii_keys_are_percent_encoded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(1)))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    for key in ["what?", "tag#1", "100%", "größe"] {
+        db.get(key).await.unwrap();
+    }
+    let paths: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| request.url.path().to_string())
+        .collect();
+    assert_eq!(
+        paths,
+        ["/database/db/what%3F", "/database/db/tag%231", "/database/db/100%25", "/database/db/gr%C3%B6%C3%9Fe"]
+    );
+}
+
+#[tokio::test]
+async fn namespaces_prefix_and_strip_keys() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/user:1:name"))
+        .and(body_json(json!("Ferris")))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user:1:name": "Ferris",
+            "user:2:name": "Corro",
+        })))
+        .mount(&server)
+        .await;
+
+    let ws_base_url = event_server_sending(json!({ "type": "set", "key": "user:1:name", "value": "Ferris" })).await;
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config(&server, ws_base_url))
+        .await
+        .unwrap();
+    let user = db.namespace("user:").namespace("1:");
+    let mut events = user.subscribe();
+    user.set("name", json!("Ferris")).await.unwrap();
+    assert_eq!(user.keys().await.unwrap(), ["name"]);
+    assert_eq!(user.get_all_entries().await.unwrap(), json!({ "name": "Ferris" }));
+    assert_eq!(
+        events.next().await,
+        Some(Event::Set { key: "name".to_string(), value: json!("Ferris") })
+    );
+}
+
+#[tokio::test]
+async fn dot_segment_keys_are_rejected() {
+    let server = MockServer::start().await;
+    let db = database(&server).await;
+    assert!(matches!(db.get("..").await, Err(WireKVSError::InvalidKey)));
+    assert!(matches!(db.delete(".").await, Err(WireKVSError::InvalidKey)));
+}
+
+#[tokio::test]
+async fn api_errors_carry_the_server_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/key"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({ "error": "value too large" })))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    match db.set("key", json!("value")).await {
+        Err(WireKVSError::Api { status, message }) => {
+            assert_eq!(status, 400);
+            assert_eq!(message, "value too large");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn oversized_responses_are_rejected() {
+    let server = MockServer::start().await;
+    let entries: serde_json::Map<_, _> = (0..100).map(|i| (format!("key{}", i), json!("value"))).collect();
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(entries))
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        max_response_size: 1024,
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    assert!(matches!(
+        db.get_all_entries().await,
+        Err(WireKVSError::ResponseTooLarge { limit: 1024 })
+    ));
+}
+
+#[tokio::test]
+async fn list_databases_sends_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases"))
+        .and(header("Authorization", "auth-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(client(&server).list_databases().await.unwrap(), json!([]));
+}
+
+#[tokio::test]
+async fn database_info_defaults_missing_fields() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases/db"))
+        .and(header("Authorization", "auth-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Scores",
+            "allowPublicReads": true,
+            "createdAt": "2024-05-01T12:00:00Z",
+        })))
+        .mount(&server)
+        .await;
+
+    let info = client(&server).get_database_info("db").await.unwrap();
+    assert_eq!(info.id, "db");
+    assert_eq!(info.name, "Scores");
+    assert_eq!(info.created_at.as_deref(), Some("2024-05-01T12:00:00Z"));
+    assert_eq!(
+        info.config,
+        DatabaseConfig {
+            allow_public_reads: true,
+            ..Default::default()
+        }
+    );
+}
+
+#[tokio::test]
+async fn database_info_rejects_bodies_without_info_fields() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "users": 3, "score": 10 })))
+        .mount(&server)
+        .await;
+
+    let err = client(&server).get_database_info("db").await.unwrap_err();
+    assert!(matches!(err, WireKVSError::Decode { .. }), "{:?}", err);
+}
+
+#[tokio::test]
+async fn config_updates_only_send_the_changed_flags() {
+    let server = MockServer::start().await;
+    Mock::given(method("PATCH"))
+        .and(path("/database/db"))
+        .and(header("Authorization", "auth-token"))
+        .and(body_json(json!({ "allowPublicWrites": false })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/databases/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "allowPublicReads": true })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let update = DatabaseConfigUpdate {
+        allow_public_writes: Some(false),
+        ..Default::default()
+    };
+    let config = client(&server).update_database_config("db", update).await.unwrap();
+    assert!(config.allow_public_reads && !config.allow_public_writes);
+}
+
+#[tokio::test]
+async fn health_check_reports_rejected_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("HEAD"))
+        .and(path("/databases"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(matches!(client(&server).health_check().await, Err(WireKVSError::Unauthorized)));
+}
+
+#[tokio::test]
+async fn coalesced_subscribers_see_the_latest_value_per_key() {
+    let server = MockServer::start().await;
+    let ws_base_url = event_server_sending_all(vec![
+        json!({ "type": "set", "key": "a", "value": 1 }),
+        json!({ "type": "set", "key": "b", "value": 1 }),
+        json!({ "type": "set", "key": "a", "value": 2 }),
+        json!({ "type": "set", "key": "a", "value": 3 }),
+    ])
+    .await;
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config(&server, ws_base_url))
+        .await
+        .unwrap();
+
+    let mut updates = db.subscribe_coalesced(Duration::from_millis(200));
+    let set = |key: &str, value| Event::Set { key: key.to_string(), value: json!(value) };
+    assert_eq!(updates.recv().await, Some(set("a", 3)));
+    assert_eq!(updates.recv().await, Some(set("b", 1)));
+    assert!(tokio::time::timeout(Duration::from_millis(300), updates.recv()).await.is_err());
+}
+
+#[tokio::test]
+async fn reconnects_replay_the_current_entries() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "score": 7 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        reconnect: ReconnectPolicy {
+            base_backoff: Duration::from_millis(10),
+            ..Default::default()
+        },
+        resync_on_reconnect: Some(Duration::from_millis(10)),
+        ..config(&server, event_server_dropping_first().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+
+    let mut events = db.subscribe_typed();
+    assert_eq!(events.recv().await.unwrap().event, Event::Disconnected);
+    assert_eq!(events.recv().await.unwrap().event, Event::Connected);
+    let resynced = tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap().unwrap();
+    assert_eq!(resynced.event, Event::Set { key: "score".to_string(), value: json!(7) });
+}
+
+#[tokio::test]
+async fn shutdown_stops_every_background_task() {
+    let server = MockServer::start().await;
+    let db = database(&server).await;
+    let tasks = || tokio::runtime::Handle::current().metrics().num_alive_tasks();
+    let before = tasks();
+    let mut filtered = db.subscribe_filtered(|_| true);
+    let mut coalesced = db.subscribe_coalesced(Duration::from_secs(1));
+    assert_eq!(tasks(), before + 2);
+
+    db.shutdown().await;
+    assert_eq!(filtered.recv().await, None);
+    assert_eq!(coalesced.recv().await, None);
+    assert_eq!(*db.connection_state().borrow(), wirekvs::ConnectionState::Disconnected);
+    // The socket task is gone as well, leaving fewer tasks than before subscribing
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while tasks() >= before {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed_and_retried() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases"))
+        .and(header("Authorization", "expired-token"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/databases"))
+        .and(header("Authorization", "fresh-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = WireKVS::builder()
+        .token("expired-token")
+        .base_url(server.uri())
+        .retry_policy(RetryPolicy::disabled())
+        .on_unauthorized(|| async { Some("fresh-token".to_string()) })
+        .build()
+        .unwrap();
+    client.list_databases().await.unwrap();
+    client.list_databases().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejected_access_keys_are_refreshed() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("Authorization", "old-key"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("Authorization", "new-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .ws_base_url(event_server().await)
+        .retry_policy(RetryPolicy::disabled())
+        .on_access_key_rejected(|id| async move { (id == "db").then(|| "new-key".to_string()) })
+        .build()
+        .unwrap();
+    let db = client.database("db".to_string(), "old-key".to_string()).await.unwrap();
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+    assert_eq!(db.clone().get("greeting").await.unwrap(), json!("Hello!"));
+    assert_eq!(db.access_key(), "new-key");
+}
+
+#[tokio::test]
+async fn event_socket_reconnects_with_the_refreshed_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("Authorization", "old-key"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .and(header("Authorization", "new-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .mount(&server)
+        .await;
+
+    // Records the URI of every handshake and closes the first connection once told to
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (uri_tx, mut uris) = tokio::sync::mpsc::unbounded_channel();
+    let (drop_tx, drop_rx) = tokio::sync::oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let mut drop_rx = Some(drop_rx);
+        while let Ok((stream, _)) = listener.accept().await {
+            let uri_tx = uri_tx.clone();
+            let drop_rx = drop_rx.take();
+            tokio::spawn(async move {
+                // The error type is fixed by tungstenite's callback signature
+                #[allow(clippy::result_large_err)]
+                let capture = |request: &tokio_tungstenite::tungstenite::handshake::server::Request, response| {
+                    let _ = uri_tx.send(request.uri().to_string());
+                    Ok(response)
+                };
+                if let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, capture).await {
+                    if let Some(drop_rx) = drop_rx {
+                        let _ = drop_rx.await;
+                        let _ = ws.close(None).await;
+                        return;
+                    }
+                    while let Some(Ok(_)) = ws.next().await {}
+                }
+            });
+        }
+    });
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .ws_base_url(format!("ws://{}/events", addr))
+        .retry_policy(RetryPolicy::disabled())
+        .reconnect_policy(ReconnectPolicy {
+            base_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .on_access_key_rejected(|_| async { Some("new-key".to_string()) })
+        .build()
+        .unwrap();
+    let db = client.database("db".to_string(), "old-key".to_string()).await.unwrap();
+    assert_eq!(uris.recv().await.unwrap(), "/events/db?accessKey=old-key");
+
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+    drop_tx.send(()).unwrap();
+    let reconnected = tokio::time::timeout(Duration::from_secs(2), uris.recv()).await.unwrap();
+    assert_eq!(reconnected.unwrap(), "/events/db?accessKey=new-key");
+}
+
+#[tokio::test]
+async fn rate_limit_fails_past_max_wait() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .rate_limit(RateLimiter::new(0.1, 1).max_wait(Duration::from_millis(50)))
+        .build()
+        .unwrap();
+    client.list_databases().await.unwrap();
+    assert!(matches!(client.list_databases().await, Err(WireKVSError::RateLimited { .. })));
+}
+
+#[tokio::test]
+async fn concurrency_limit_is_shared_by_databases_of_a_client() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!("Hello!"))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .ws_base_url(event_server().await)
+        .retry_policy(RetryPolicy::disabled())
+        .concurrency_limit(ConcurrencyLimit::new(1))
+        .build()
+        .unwrap();
+    let first = client.database("db".to_string(), "access-key".to_string()).await.unwrap();
+    let second = client.database("other".to_string(), "access-key".to_string()).await.unwrap();
+
+    let started = std::time::Instant::now();
+    let (a, b) = tokio::join!(first.get("greeting"), second.get("greeting"));
+    assert_eq!(a.unwrap(), json!("Hello!"));
+    assert_eq!(b.unwrap(), json!("Hello!"));
+    assert!(started.elapsed() >= Duration::from_millis(400));
+}
+
+#[test]
+fn zero_concurrency_limit_is_rejected() {
+    let result = WireKVS::builder()
+        .token("auth-token")
+        .concurrency_limit(ConcurrencyLimit::new(0))
+        .build();
+    assert!(matches!(result, Err(WireKVSError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn decode_errors_show_the_start_of_the_body() {
+    let server = MockServer::start().await;
+    let page = format!("<html><body>502 Bad Gateway{}</body></html>", " ".repeat(1000));
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(page))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    match db.get("greeting").await {
+        Err(WireKVSError::Decode {
+            body_snippet: Some(snippet),
+            ..
+        }) => {
+            assert!(snippet.starts_with("<html><body>502 Bad Gateway"));
+            assert!(snippet.ends_with("..."));
+            assert!(snippet.len() <= 256 + 3);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn increment_reads_once_per_attempt() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/count"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(5)).insert_header("ETag", "\"1\""))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    // Another client bumped the counter after the first read
+    Mock::given(method("GET"))
+        .and(path("/database/db/count"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(7)).insert_header("ETag", "\"2\""))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/count"))
+        .and(header("If-Match", "\"1\""))
+        .respond_with(ResponseTemplate::new(412))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/count"))
+        .and(header("If-Match", "\"2\""))
+        .and(body_json(json!(8)))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.increment("count", 1).await.unwrap(), 8);
+}
+
+#[tokio::test]
+async fn array_append_writes_the_extended_array() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/tags"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(["crab"])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/tags"))
+        .and(body_json(json!(["crab", "rust"])))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.array_append("tags", json!("rust")).await.unwrap(), json!(["crab", "rust"]));
+    assert_eq!(db.array_remove("tags", &json!("ferris")).await.unwrap(), json!(["crab"]));
+}
+
+#[tokio::test]
+async fn set_if_absent_skips_existing_keys() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/lock"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("owner-1")))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert!(!db.set_if_absent("lock", json!("owner-2")).await.unwrap());
+}
+
+#[tokio::test]
+async fn set_if_absent_reports_a_failed_precondition() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/lock"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/lock"))
+        .and(header("If-None-Match", "*"))
+        .respond_with(ResponseTemplate::new(412))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert!(!db.set_if_absent("lock", json!("owner-2")).await.unwrap());
+}
+
+#[tokio::test]
+async fn delete_many_names_the_keys_that_failed() {
+    let server = MockServer::start().await;
+    Mock::given(method("DELETE"))
+        .and(path("/database/db/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    match db.delete_many(&["first", "missing", "second"]).await {
+        Err(WireKVSError::Batch { failures }) => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].0, "missing");
+            assert!(matches!(failures[0].1, WireKVSError::NotFound));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn slow_responses_time_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/databases"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])).set_delay(Duration::from_secs(2)))
+        .mount(&server)
+        .await;
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .timeout(Duration::from_millis(100))
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+    assert!(matches!(client.list_databases().await, Err(WireKVSError::Timeout)));
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn gzip_bodies_are_decompressed() {
+    // `"Hello!"` compressed with gzip
+    let compressed = vec![
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x53, 0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x54, 0x02,
+        0x00, 0x58, 0x15, 0xea, 0xc6, 0x08, 0x00, 0x00, 0x00,
+    ];
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Encoding", "gzip")
+                .set_body_raw(compressed, "application/json"),
+        )
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+}
+
+#[tokio::test]
+async fn too_many_requests_reports_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/busy"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    match db.get("busy").await {
+        Err(WireKVSError::RateLimited { retry_after }) => assert_eq!(retry_after, Duration::from_secs(7)),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn too_many_requests_is_retried_after_the_delay() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/busy"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/busy"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("done")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        retry: RetryPolicy::default(),
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    assert_eq!(db.get("busy").await.unwrap(), json!("done"));
+}
+
+#[tokio::test]
+async fn set_idempotent_retries_with_the_same_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/balance"))
+        .and(header("Idempotency-Key", "deposit-1"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/balance"))
+        .and(header("Idempotency-Key", "deposit-1"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        },
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    db.set_idempotent("balance", json!(120), "deposit-1").await.unwrap();
+}
+
+#[test]
+fn malformed_certificates_are_rejected() {
+    assert!(matches!(Certificate::from_pem(b"not a certificate"), Err(WireKVSError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn database_config_is_validated_before_building_the_client() {
+    let config = WireKVSConfig {
+        http_proxy: Some("not a proxy".to_string()),
+        ..Default::default()
+    };
+    let result = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config).await;
+    assert!(matches!(result, Err(WireKVSError::InvalidConfig(_))));
+}
+
+#[test]
+fn client_config_is_validated() {
+    let config = WireKVSConfig {
+        https_proxy: Some("not a proxy".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(WireKVS::with_config("auth-token".to_string(), config), Err(WireKVSError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn client_and_its_databases_reuse_one_connection() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Answers every request with `[]` over keep-alive connections, counting how many are opened
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    while let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        request.drain(..end + 4);
+                        let response = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n[]";
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(format!("http://{}", addr))
+        .ws_base_url(event_server().await)
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+    let users = client.database("users".to_string(), "access-key".to_string()).await.unwrap();
+    let orders = client.database("orders".to_string(), "access-key".to_string()).await.unwrap();
+
+    client.list_databases().await.unwrap();
+    users.get("greeting").await.unwrap();
+    orders.get("greeting").await.unwrap();
+    client.list_databases().await.unwrap();
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn malformed_base_url_is_rejected() {
+    let result = WireKVS::builder().token("auth-token").base_url("not a url").build();
+    assert!(matches!(result, Err(WireKVSError::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn refused_connections_are_network_errors() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(format!("http://{}", addr))
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+    assert!(matches!(client.list_databases().await, Err(WireKVSError::Network(_))));
+}
+
+#[tokio::test]
+async fn create_database_sends_permissions() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database"))
+        .and(header("Authorization", "auth-token"))
+        .and(body_json(json!({
+            "name": "Demo",
+            "allowPublicWrites": false,
+            "allowPublicReads": true,
+            "allowPublicModifications": false,
+            "allowSpecificPublicReads": false,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "kvsId": "new-id", "accessKey": "new-key" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = DatabaseConfig {
+        allow_public_reads: true,
+        ..Default::default()
+    };
+    let created = client(&server).create_database("Demo", config).await.unwrap();
+    assert_eq!(created.id, "new-id");
+    assert_eq!(created.access_key, "new-key");
+    assert_eq!(created.name, "Demo");
+}
+
+#[tokio::test]
+async fn delete_database_targets_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("DELETE"))
+        .and(path("/database/old-id"))
+        .and(header("Authorization", "auth-token"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client(&server).delete_database("old-id").await.unwrap();
+}
+
+#[tokio::test]
+async fn events_before_the_first_subscribe_are_kept() {
+    let server = MockServer::start().await;
+    let ws_base_url = event_server_sending(json!({ "type": "set", "key": "greeting", "value": "Hello!" })).await;
+    let config = config(&server, ws_base_url);
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut rx = db.subscribe_typed();
+    let event = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(event.event, Event::Set { key: "greeting".to_string(), value: json!("Hello!") });
+}
+
+#[cfg(feature = "history")]
+#[tokio::test]
+async fn history_lists_revisions_oldest_first() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/config/history"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "version": 2, "value": "b", "updatedAt": "2025-01-02T00:00:00Z" },
+            { "version": 1, "value": "a" },
+        ])))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let revisions = db.history("config").await.unwrap();
+    assert_eq!(revisions.iter().map(|r| r.version).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(revisions[1].updated_at.as_deref(), Some("2025-01-02T00:00:00Z"));
+}
+
+#[cfg(feature = "history")]
+#[tokio::test]
+async fn get_version_reports_unsupported_servers() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/config"))
+        .and(wiremock::matchers::query_param("version", "3"))
+        .respond_with(ResponseTemplate::new(501))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert!(matches!(db.get_version("config", 3).await, Err(WireKVSError::Unsupported { .. })));
+}
+
+#[cfg(feature = "history")]
+#[tokio::test]
+async fn delete_if_match_reports_a_moved_on_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("DELETE"))
+        .and(path("/database/db/lock"))
+        .and(header("If-Match", "\"7\""))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/database/db/lock"))
+        .and(header("If-Match", "\"6\""))
+        .respond_with(ResponseTemplate::new(412))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    assert!(db.delete_if_match("lock", 7).await.unwrap());
+    assert!(!db.delete_if_match("lock", 6).await.unwrap());
+}
+
+#[tokio::test]
+async fn send_ws_writes_a_text_frame() {
+    let server = MockServer::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_base_url = format!("ws://{}/events", listener.local_addr().unwrap());
+    let (tx, mut received) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(Ok(message)) = ws.next().await {
+            if let Message::Text(text) = message {
+                let _ = tx.send(text.to_string());
+            }
+        }
+    });
+
+    let config = config(&server, ws_base_url);
+    let mut db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    db.send_ws(json!({ "type": "ping" })).await.unwrap();
+    assert_eq!(received.recv().await.unwrap(), r#"{"type":"ping"}"#);
+
+    db.close().await;
+    assert!(matches!(db.send_ws(json!({})).await, Err(WireKVSError::NotConnected)));
+}
+
+#[cfg(feature = "cache")]
+#[tokio::test]
+async fn concurrent_cache_misses_share_one_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!("Hello!"))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        cache_capacity: Some(10),
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    let (first, second, third) = tokio::join!(db.get("greeting"), db.get("greeting"), db.get("greeting"));
+    assert_eq!(first.unwrap(), json!("Hello!"));
+    assert_eq!(second.unwrap(), json!("Hello!"));
+    assert_eq!(third.unwrap(), json!("Hello!"));
+}
+
+#[cfg(feature = "cache")]
+#[tokio::test]
+async fn failed_shared_requests_are_not_cached() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(500).set_delay(Duration::from_millis(200)))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        cache_capacity: Some(10),
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    let (first, second) = tokio::join!(db.get("greeting"), db.get("greeting"));
+    assert!(first.is_err());
+    assert!(second.is_err());
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+}
+
+#[tokio::test]
+async fn socket_url_carries_extra_query_params() {
+    let server = MockServer::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (uri_tx, uri_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        // The error type is fixed by tungstenite's callback signature
+        #[allow(clippy::result_large_err)]
+        let capture = |request: &tokio_tungstenite::tungstenite::handshake::server::Request, response| {
+            let _ = uri_tx.send(request.uri().to_string());
+            Ok(response)
+        };
+        if let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, capture).await {
+            while let Some(Ok(_)) = ws.next().await {}
+        }
+    });
+
+    let config = WireKVSConfig {
+        ws_query_params: vec![("filter".to_string(), "user:*".to_string()), ("v".to_string(), "2".to_string())],
+        ..config(&server, format!("ws://{}/events", addr))
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    assert_eq!(uri_rx.await.unwrap(), "/events/db?accessKey=access-key&filter=user%3A*&v=2");
+    assert_eq!(
+        db.event_socket_url().as_str(),
+        format!("ws://{}/events/db?accessKey=redacted&filter=user%3A*&v=2", addr)
+    );
+}
+
+#[tokio::test]
+async fn wait_for_checks_the_current_value_first() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/job"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("done")))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let done = db.wait_for("job", |status| status == &json!("done"), Duration::from_secs(5));
+    assert_eq!(done.await.unwrap(), json!("done"));
+    let failed = db.wait_for("job", |status| status == &json!("failed"), Duration::from_millis(100));
+    assert!(matches!(failed.await, Err(WireKVSError::Timeout)));
+}
+
+#[tokio::test]
+async fn connect_many_lists_the_databases_that_failed() {
+    let server = MockServer::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (closed_tx, mut closed) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let closed_tx = closed_tx.clone();
+            tokio::spawn(async move {
+                let (path_tx, path_rx) = tokio::sync::oneshot::channel();
+                // Only the "broken" database refuses the handshake
+                #[allow(clippy::result_large_err)]
+                let reject_broken = |request: &tokio_tungstenite::tungstenite::handshake::server::Request, response| {
+                    let _ = path_tx.send(request.uri().path().to_string());
+                    if request.uri().path() == "/events/broken" {
+                        let mut refused = tokio_tungstenite::tungstenite::handshake::server::ErrorResponse::new(None);
+                        *refused.status_mut() = tokio_tungstenite::tungstenite::http::StatusCode::FORBIDDEN;
+                        return Err(refused);
+                    }
+                    Ok(response)
+                };
+                if let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, reject_broken).await {
+                    while let Some(Ok(message)) = ws.next().await {
+                        if message.is_close() {
+                            let _ = closed_tx.send(path_rx.await.unwrap());
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let client = WireKVS::with_config("auth-token".to_string(), config(&server, format!("ws://{}/events", addr))).unwrap();
+    let creds = |ids: &[&str]| ids.iter().map(|id| (id.to_string(), "key".to_string())).collect::<Vec<_>>();
+
+    let dbs = client.connect_many(creds(&["users", "orders"])).await.unwrap();
+    assert_eq!(dbs.iter().map(WireKVSDatabase::id).collect::<Vec<_>>(), ["users", "orders"]);
+
+    match client.connect_many(creds(&["users", "broken"])).await {
+        Err(WireKVSError::Batch { failures }) => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].0, "broken");
+        }
+        other => panic!("expected a batch error, got {:?}", other.map(|dbs| dbs.len())),
+    }
+    // The database that did connect sent its close frame before the error came back
+    let closed = tokio::time::timeout(Duration::from_secs(1), closed.recv()).await.unwrap();
+    assert_eq!(closed.unwrap(), "/events/users");
+    drop(dbs);
+}
+
+#[tokio::test]
+async fn typed_events_keep_fields_the_event_does_not_model() {
+    let server = MockServer::start().await;
+    let frame = json!({ "type": "set", "key": "greeting", "value": "Hello!", "revision": 7 });
+    let ws_base_url = event_server_sending(frame.clone()).await;
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config(&server, ws_base_url))
+        .await
+        .unwrap();
+
+    let envelope = db.subscribe_typed().recv().await.unwrap();
+    assert_eq!(envelope.event, Event::Set { key: "greeting".to_string(), value: json!("Hello!") });
+    assert_eq!(*envelope.raw, frame);
+}
+
+#[tokio::test]
+async fn expiry_events_are_kept_apart_from_deletes() {
+    let server = MockServer::start().await;
+    let ws_base_url = event_server_sending_all(vec![
+        json!({ "type": "expired", "key": "session" }),
+        json!({ "type": "delete", "key": "greeting" }),
+    ])
+    .await;
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config(&server, ws_base_url))
+        .await
+        .unwrap();
+
+    let mut events = db.subscribe_typed();
+    assert_eq!(events.recv().await.unwrap().event, Event::Expired { key: "session".to_string() });
+    assert_eq!(events.recv().await.unwrap().event, Event::Deleted { key: "greeting".to_string() });
+}
+
+#[tokio::test]
+async fn multiplexed_events_are_tagged_with_their_database() {
+    let server = MockServer::start().await;
+    let ws_base_url = event_server_sending(json!({ "type": "delete", "key": "greeting" })).await;
+    let client = WireKVS::builder()
+        .token("auth-token")
+        .base_url(server.uri())
+        .ws_base_url(ws_base_url)
+        .build()
+        .unwrap();
+    let users = client.database("users".to_string(), "access-key".to_string()).await.unwrap();
+    let orders = client.database("orders".to_string(), "access-key".to_string()).await.unwrap();
+
+    let events = client.multiplex_events(&[&users, &orders]);
+    let mut ids: Vec<String> = events
+        .filter(|(_, event)| futures::future::ready(matches!(event, Event::Deleted { .. })))
+        .map(|(id, _)| id)
+        .take(2)
+        .collect()
+        .await;
+    ids.sort();
+    assert_eq!(ids, vec!["orders".to_string(), "users".to_string()]);
+}
+
+#[tokio::test]
+async fn base_url_path_prefixes_are_kept() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/kvs/v2/database/db/greeting"))
+        .and(body_json(json!("Hello!")))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/kvs/v2/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        base_url: format!("{}/kvs/v2/", server.uri()),
+        ..config(&server, event_server().await.replace("/events", "/kvs/events/"))
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    db.set("greeting", json!("Hello!")).await.unwrap();
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+    assert_eq!(db.event_socket_url().path(), "/kvs/events/db");
+}
+
+#[tokio::test]
+async fn entries_stream_yields_transient_errors_and_carries_on() {
+    use wiremock::matchers::{query_param, query_param_is_missing};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .and(query_param_is_missing("cursor"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "entries": [{ "key": "a", "value": 1 }, { "key": "b", "value": 2 }],
+            "nextCursor": "page-2",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .and(query_param("cursor", "page-2"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db"))
+        .and(query_param("cursor", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "entries": [{ "key": "c", "value": 3 }],
+            "nextCursor": null,
+        })))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let entries: Vec<_> = db.entries_stream().collect().await;
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].as_ref().unwrap(), &("a".to_string(), json!(1)));
+    assert_eq!(entries[1].as_ref().unwrap(), &("b".to_string(), json!(2)));
+    assert!(matches!(entries[2], Err(WireKVSError::Api { status: 503, .. })));
+    assert_eq!(entries[3].as_ref().unwrap(), &("c".to_string(), json!(3)));
+}
+
+#[cfg(feature = "cache")]
+#[tokio::test]
+async fn read_your_writes_serves_the_written_value() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let config = WireKVSConfig {
+        cache_capacity: Some(10),
+        read_your_writes: true,
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    db.set("greeting", json!("Hi!")).await.unwrap();
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hi!"));
+}
+
+#[tokio::test]
+async fn diagnostics_count_events_and_reconnects() {
+    let server = MockServer::start().await;
+    let config = config(&server, event_server_dropping_first().await);
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    let before = db.connection_diagnostics();
+    assert!(before.connected);
+    assert_eq!(before.reconnect_count, 0);
+    assert_eq!(before.last_event_at, None);
+
+    let mut events = db.subscribe_typed();
+    assert_eq!(events.recv().await.unwrap().event, Event::Disconnected);
+    assert_eq!(events.recv().await.unwrap().event, Event::Connected);
+    let after = db.connection_diagnostics();
+    assert!(after.connected);
+    assert_eq!(after.reconnect_count, 1);
+    assert_eq!(after.events_received, 0);
+    assert_eq!(after.subscriber_count, 1);
+}
+
+#[tokio::test]
+async fn set_raw_sends_the_text_unchanged() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/database/db/profile"))
+        .and(header("Content-Type", "application/json"))
+        .and(wiremock::matchers::body_string(r#"{ "name": "Ferris" }"#))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    db.set_raw("profile", r#"{ "name": "Ferris" }"#).await.unwrap();
+    assert!(matches!(db.set_raw("profile", "{ name: Ferris }").await, Err(WireKVSError::Decode { body_snippet: None, .. })));
+}
+
+#[tokio::test]
+async fn socket_handshake_can_carry_the_key_and_subprotocols_in_headers() {
+    use tokio_tungstenite::tungstenite::handshake::server::Request;
+    use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+    let server = MockServer::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (request_tx, request_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]
+        let capture = |request: &Request, mut response: tokio_tungstenite::tungstenite::handshake::server::Response| {
+            let header = |name| request.headers().get(name).map(|value: &HeaderValue| value.to_str().unwrap().to_string());
+            let _ = request_tx.send((request.uri().to_string(), header("Authorization"), header("Sec-WebSocket-Protocol")));
+            response.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static("wirekvs.v2"));
+            Ok(response)
+        };
+        if let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, capture).await {
+            while let Some(Ok(_)) = ws.next().await {}
+        }
+    });
+
+    let config = WireKVSConfig {
+        ws_auth: SocketAuth::Header,
+        ws_subprotocols: vec!["wirekvs.v2".to_string(), "wirekvs.v1".to_string()],
+        ..config(&server, format!("ws://{}/events", addr))
+    };
+    let _db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    let (uri, authorization, protocols) = request_rx.await.unwrap();
+    assert_eq!(uri, "/events/db");
+    assert_eq!(authorization.as_deref(), Some("access-key"));
+    assert_eq!(protocols.as_deref(), Some("wirekvs.v2, wirekvs.v1"));
+}
+
+#[tokio::test]
+async fn graceful_shutdown_reports_undelivered_events() {
+    let server = MockServer::start().await;
+    let events = (1..=3).map(|n| json!({ "type": "set", "key": "count", "value": n })).collect();
+    let config = config(&server, event_server_sending_all(events).await);
+    let mut db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    let mut rx = db.subscribe_typed();
+    let (first_tx, first_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let _ = first_tx.send(rx.recv().await.unwrap());
+        // Keeps the receiver, and the two events behind the first, unread
+        std::future::pending::<()>().await;
+    });
+    assert_eq!(first_rx.await.unwrap().event, Event::Set { key: "count".to_string(), value: json!(1) });
+    while db.connection_diagnostics().events_received < 3 {
+        tokio::task::yield_now().await;
+    }
+
+    let report = db.graceful_shutdown(Duration::from_millis(50)).await;
+    assert_eq!(report, wirekvs::ShutdownReport { delivered: 0, dropped: 2 });
+    assert!(!db.is_connected());
+}
+
+#[tokio::test]
+async fn value_envelopes_are_split_from_their_metadata() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "value": { "theme": "dark" },
+            "version": 3,
+            "updatedAt": "2025-01-02T00:00:00Z",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/greeting"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!("Hello!")))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let settings = db.get_with_metadata("config").await.unwrap();
+    assert_eq!(settings.value, json!({ "theme": "dark" }));
+    assert_eq!(settings.version, Some(3));
+    assert_eq!(settings.updated_at.as_deref(), Some("2025-01-02T00:00:00Z"));
+    let greeting = db.get_with_metadata("greeting").await.unwrap();
+    assert_eq!((greeting.value, greeting.version), (json!("Hello!"), None));
+    assert_eq!(db.get("config").await.unwrap()["version"], json!(3));
+
+    let config = WireKVSConfig {
+        value_envelopes: true,
+        ..config(&server, event_server().await)
+    };
+    let db = WireKVSDatabase::with_config("db".to_string(), "access-key".to_string(), config)
+        .await
+        .unwrap();
+    assert_eq!(db.get("config").await.unwrap(), json!({ "theme": "dark" }));
+    assert_eq!(db.get("greeting").await.unwrap(), json!("Hello!"));
+}
+
+#[cfg(feature = "chrono")]
+#[tokio::test]
+async fn timestamps_parse_from_rfc3339_and_epoch_millis() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/rfc3339"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "value": 1, "updatedAt": "2025-01-02T01:00:00+01:00" })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/database/db/millis"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "value": 2, "updatedAt": 1735776000000u64 })))
+        .mount(&server)
+        .await;
+
+    let db = database(&server).await;
+    let expected = chrono::DateTime::parse_from_rfc3339("2025-01-02T00:00:00Z").unwrap();
+    let rfc3339 = db.get_with_metadata("rfc3339").await.unwrap();
+    assert_eq!(rfc3339.updated_at_utc(), Some(expected.to_utc()));
+    let millis = db.get_with_metadata("millis").await.unwrap();
+    assert_eq!(millis.updated_at.as_deref(), Some("1735776000000"));
+    assert_eq!(millis.updated_at_utc(), Some(expected.to_utc()));
+}
@@ -0,0 +1,78 @@
+//! Checks the in-memory database against the behavior of the real one
+
+#![cfg(feature = "in-memory")]
+
+use std::time::Duration;
+
+use serde_json::json;
+use wirekvs::{Event, InMemoryDatabase, KeyValueStore, WireKVSError};
+
+#[tokio::test]
+async fn writes_fire_events() {
+    let db = InMemoryDatabase::new();
+    let mut rx = db.subscribe_typed();
+
+    db.set("greeting", json!("Hello!")).await.unwrap();
+    db.delete("greeting").await.unwrap();
+    db.delete("missing").await.unwrap();
+
+    assert_eq!(rx.recv().await.unwrap().event, Event::Set { key: "greeting".to_string(), value: json!("Hello!") });
+    assert_eq!(rx.recv().await.unwrap().event, Event::Deleted { key: "greeting".to_string() });
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn errors_match_the_real_backend() {
+    let db = InMemoryDatabase::new();
+    db.set("name", json!("Ferris")).await.unwrap();
+
+    assert!(matches!(db.get("missing").await, Err(WireKVSError::NotFound)));
+    assert!(matches!(db.set("", json!(1)).await, Err(WireKVSError::InvalidKey)));
+    assert!(matches!(db.increment("name", 1).await, Err(WireKVSError::TypeMismatch { .. })));
+    assert!(matches!(db.array_append("name", json!(1)).await, Err(WireKVSError::TypeMismatch { .. })));
+}
+
+#[tokio::test]
+async fn set_if_absent_keeps_the_first_value() {
+    let db = InMemoryDatabase::new();
+    assert!(db.set_if_absent("lock", json!("owner-1")).await.unwrap());
+    assert!(!db.set_if_absent("lock", json!("owner-2")).await.unwrap());
+    assert_eq!(db.get("lock").await.unwrap(), json!("owner-1"));
+}
+
+#[tokio::test]
+async fn late_first_subscriber_starts_at_the_live_position() {
+    let db = InMemoryDatabase::with_event_buffer_size(4);
+    for n in 0..10 {
+        db.set("count", json!(n)).await.unwrap();
+    }
+
+    let mut rx = db.subscribe_typed();
+    db.set("count", json!(10)).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap().event, Event::Set { key: "count".to_string(), value: json!(10) });
+}
+
+#[tokio::test]
+async fn works_behind_the_store_trait() {
+    let store: Box<dyn KeyValueStore> = Box::new(InMemoryDatabase::new());
+    store.set("count", json!(1)).await.unwrap();
+    assert_eq!(store.get_all_entries().await.unwrap(), json!({ "count": 1 }));
+}
+
+#[tokio::test]
+async fn wait_for_resolves_on_a_matching_write() {
+    let db = InMemoryDatabase::new();
+    db.set("job", json!("pending")).await.unwrap();
+
+    let writer = db.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        writer.set("job", json!("running")).await.unwrap();
+        writer.set("job", json!("done")).await.unwrap();
+    });
+    let done = db.wait_for("job", |status| status == &json!("done"), Duration::from_secs(5));
+    assert_eq!(done.await.unwrap(), json!("done"));
+
+    let never = db.wait_for("job", |status| status == &json!("failed"), Duration::from_millis(50));
+    assert!(matches!(never.await, Err(WireKVSError::Timeout)));
+}